@@ -0,0 +1,251 @@
+use rand::Rng;
+
+use entity::{attack, move_by, next_to, step_towards, Position};
+use game::{GameEvent, GameState};
+use map::Map;
+use world::{EntityId, World};
+
+/// How close the player has to get to a sleeping monster it can see before it wakes up.
+const WAKE_DISTANCE: i32 = 3;
+/// How far from home a wandering monster strays.
+const WANDER_RADIUS: i32 = 5;
+/// How far monsters can see when their template doesn't say.
+pub const DEFAULT_SIGHT_RADIUS: i32 = 8;
+/// How far away a fight can be heard.
+pub const COMBAT_NOISE: i32 = 10;
+
+/// How a kind of monster behaves, picked per template in `monsters.toml`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Behavior {
+    /// Stands still, and chases the player for as long as it can see them.
+    Basic,
+    /// Sleeps until the player comes close or hurts it, and goes back to bed once it loses
+    /// track of them.
+    Sleeper,
+    /// Roams around where it spawned, and goes back there once it loses track of the player.
+    Wanderer,
+}
+
+/// What a monster is up to right now.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AiState {
+    Idle,
+    Asleep,
+    Wandering { goal: Option<(i32, i32)> },
+    /// Going after the player, or to where the player was last seen.
+    Hunting { last_seen: (i32, i32) },
+    Fleeing,
+    Returning,
+}
+
+/// Something monsters nearby might hear, made at `pos` and carrying `loudness` tiles.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Noise {
+    pub pos: (i32, i32),
+    pub loudness: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Ai {
+    pub behavior: Behavior,
+    pub state: AiState,
+    /// Where the monster spawned, which it returns to after a hunt.
+    pub home: (i32, i32),
+    /// Below this fraction of its max hp the monster runs away. 0 means it never does.
+    pub flee_below: f32,
+    /// How far the monster can see the player, light or not.
+    pub sight_radius: i32,
+    /// Turns left stumbling around at random, after being hit by a confusion spell.
+    pub confused: i32,
+}
+
+impl Ai {
+    pub fn new(behavior: Behavior, home: (i32, i32), flee_below: f32, sight_radius: i32) -> Self {
+        Ai {
+            behavior,
+            state: resting_state(behavior),
+            home,
+            flee_below,
+            sight_radius,
+            confused: 0,
+        }
+    }
+
+    /// Makes the monster come after whoever is at `pos`, e.g. because they just hit it.
+    pub fn alert(&mut self, pos: (i32, i32)) {
+        if self.state != AiState::Fleeing {
+            self.state = AiState::Hunting { last_seen: pos };
+        }
+    }
+
+    /// Whether a monster at `pos` would hear `noise`. Sleeping monsters only wake up to
+    /// noises half as far away.
+    pub fn hears(&self, pos: (i32, i32), noise: &Noise) -> bool {
+        let range = if self.state == AiState::Asleep { noise.loudness / 2 } else { noise.loudness };
+        (pos.0 - noise.pos.0).pow(2) + (pos.1 - noise.pos.1).pow(2) <= range.pow(2)
+    }
+
+    /// Whether a monster at `pos` can see `target`: it has to be within the monster's sight
+    /// radius with nothing blocking the view. Sleeping monsters only notice what's right by them.
+    pub fn sees(&self, pos: (i32, i32), target: (i32, i32), map: &Map) -> bool {
+        let radius = if self.state == AiState::Asleep { WAKE_DISTANCE } else { self.sight_radius };
+        (pos.0 - target.0).pow(2) + (pos.1 - target.1).pow(2) <= radius.pow(2)
+            && map.line_of_sight(pos, target)
+    }
+
+    pub fn take_turn(monster: EntityId, state: &mut GameState, events: &mut Vec<GameEvent>) {
+        let depth = state.depth;
+        let world = &mut state.world;
+        let level = &mut state.levels[depth];
+        let mut ai = match world.ais.get(monster) {
+            Some(&ai) => ai,
+            None => return,
+        };
+        let others: Vec<(i32, i32)> = world.on_level(depth).into_iter()
+            .filter(|&id| id != monster && world.ais.has(id) && world.blocks.has(id))
+            .filter_map(|id| world.position(id))
+            .map(Position::xy)
+            .collect();
+        let diagonal = state.options.diagonal_movement;
+        let player = state.player;
+        let pos = world.position(monster).map_or((0, 0), Position::xy);
+
+        if ai.confused > 0 {
+            ai.confused -= 1;
+            let (dx, dy) = (state.rng.gen_range(-1, 2), state.rng.gen_range(-1, 2));
+            if (diagonal || dx == 0 || dy == 0) && !level.map.cuts_corner(pos.0, pos.1, dx, dy) {
+                world.paths.remove(monster);
+                move_by(world, monster, dx, dy, &mut level.map);
+            }
+            if ai.confused == 0 {
+                events.push(GameEvent::ConfusionEnded(world.name(monster).to_string()));
+            }
+            world.ais.insert(monster, ai);
+            return;
+        }
+
+        let player_pos = world.position(player).map_or((0, 0), Position::xy);
+        let sees_player = ai.sees(pos, player_pos, &level.map);
+        let heard = level.noises.iter().rev()
+            .find(|noise| ai.hears(pos, noise))
+            .map(|noise| noise.pos);
+        let hurt = world.fighters.get(monster).is_some_and(|f| (f.hp as f32) < f.max_hp() as f32 * ai.flee_below);
+        let player_alive = world.fighters.get(player).is_some_and(|f| f.hp > 0);
+
+        ai.state = match (ai.state, heard) {
+            _ if sees_player && hurt => AiState::Fleeing,
+            _ if sees_player => AiState::Hunting { last_seen: player_pos },
+            (AiState::Fleeing, _) => AiState::Returning,
+            // Basic monsters only go after what they can see
+            (AiState::Hunting { .. }, _) if ai.behavior == Behavior::Basic => resting_state(ai.behavior),
+            // something's going on over there, go and have a look
+            (_, Some(pos)) if ai.behavior != Behavior::Basic => AiState::Hunting { last_seen: pos },
+            (AiState::Hunting { last_seen }, _) if pos == last_seen => AiState::Returning,
+            (state, _) => state,
+        };
+
+        let moved = |world: &World| world.position(monster).is_some_and(|p| p.xy() != pos);
+        match ai.state {
+            AiState::Idle | AiState::Asleep => {},
+            AiState::Wandering { goal } => {
+                let goal = match goal {
+                    Some(goal) if goal != pos => goal,
+                    _ => wander_goal(ai.home, &level.map, &mut state.rng),
+                };
+                step_towards(world, monster, goal, &mut level.map, &others, diagonal);
+                // a goal it can't get to is given up on, it'll pick another one next turn
+                ai.state = AiState::Wandering { goal: if moved(world) { Some(goal) } else { None } };
+            },
+            AiState::Hunting { last_seen } => {
+                if sees_player && next_to(world, monster, player, &level.map, diagonal) {
+                    if player_alive {
+                        attack(world, monster, player, false, events);
+                        level.noises.push(Noise { pos, loudness: COMBAT_NOISE });
+                    }
+                } else {
+                    step_towards(world, monster, last_seen, &mut level.map, &others, diagonal);
+                    if !sees_player && !moved(world) {
+                        ai.state = AiState::Returning;
+                    }
+                }
+            },
+            AiState::Fleeing => {
+                if !step_away(world, monster, player_pos, &mut level.map, diagonal) && next_to(world, monster, player, &level.map, diagonal) {
+                    // cornered
+                    if player_alive {
+                        attack(world, monster, player, false, events);
+                        level.noises.push(Noise { pos, loudness: COMBAT_NOISE });
+                    }
+                }
+            },
+            AiState::Returning => {
+                step_towards(world, monster, ai.home, &mut level.map, &others, diagonal);
+                if !moved(world) {
+                    // home is taken or out of reach, this spot will do
+                    ai.state = resting_state(ai.behavior);
+                }
+            },
+        }
+
+        // attacking the player can't have killed the monster, so it still has its ai
+        world.ais.insert(monster, ai);
+    }
+}
+
+fn resting_state(behavior: Behavior) -> AiState {
+    match behavior {
+        Behavior::Basic => AiState::Idle,
+        Behavior::Sleeper => AiState::Asleep,
+        Behavior::Wanderer => AiState::Wandering { goal: None },
+    }
+}
+
+/// A free spot near `home` to wander to, or `home` itself if none turned up.
+fn wander_goal<R: Rng>(home: (i32, i32), map: &Map, rng: &mut R) -> (i32, i32) {
+    for _ in 0..10 {
+        let x = home.0 + rng.gen_range(-WANDER_RADIUS, WANDER_RADIUS + 1);
+        let y = home.1 + rng.gen_range(-WANDER_RADIUS, WANDER_RADIUS + 1);
+        if map.contains(x, y) && !map.get(x, y).blocks_movement {
+            return (x, y);
+        }
+    }
+    home
+}
+
+/// Moves `monster` to whichever neighbouring tile is furthest from `threat`. Returns `false` if
+/// there's nowhere to go that's further away than where it is.
+fn step_away(world: &mut World, monster: EntityId, threat: (i32, i32), map: &mut Map, diagonal: bool) -> bool {
+    let (mx, my) = match world.position(monster) {
+        Some(pos) => pos.xy(),
+        None => return false,
+    };
+    let distance = |(x, y): (i32, i32)| (x - threat.0).pow(2) + (y - threat.1).pow(2);
+    let directions: &[(i32, i32)] = if diagonal {
+        &[(0, -1), (0, 1), (-1, 0), (1, 0), (-1, -1), (1, -1), (-1, 1), (1, 1)]
+    } else {
+        &[(0, -1), (0, 1), (-1, 0), (1, 0)]
+    };
+
+    let mut best = None;
+    let mut best_distance = distance((mx, my));
+    for &(dx, dy) in directions {
+        let (x, y) = (mx + dx, my + dy);
+        if map.get(x, y).blocks_movement || map.cuts_corner(mx, my, dx, dy) {
+            continue;
+        }
+        if distance((x, y)) > best_distance {
+            best = Some((dx, dy));
+            best_distance = distance((x, y));
+        }
+    }
+
+    match best {
+        Some((dx, dy)) => {
+            world.paths.remove(monster);
+            move_by(world, monster, dx, dy, map);
+            true
+        },
+        None => false,
+    }
+}
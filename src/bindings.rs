@@ -0,0 +1,229 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::Path;
+
+use tcod::input::{Key, KeyCode};
+
+use data::read_toml;
+use game::Action;
+
+pub const BINDINGS_FILE: &str = "keys.toml";
+
+/// Everything a player can ask for with a key press.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Command {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    MoveUpLeft,
+    MoveUpRight,
+    MoveDownLeft,
+    MoveDownRight,
+    DrinkPotion,
+    Equip,
+    Inventory,
+    Look,
+    History,
+    Wait,
+    Start,
+    Exit,
+}
+
+// names used for commands in the bindings file
+const COMMAND_NAMES: [(Command, &str); 16] = [
+    (Command::MoveUp, "move_up"),
+    (Command::MoveDown, "move_down"),
+    (Command::MoveLeft, "move_left"),
+    (Command::MoveRight, "move_right"),
+    (Command::MoveUpLeft, "move_up_left"),
+    (Command::MoveUpRight, "move_up_right"),
+    (Command::MoveDownLeft, "move_down_left"),
+    (Command::MoveDownRight, "move_down_right"),
+    (Command::DrinkPotion, "drink_potion"),
+    (Command::Equip, "equip"),
+    (Command::Inventory, "inventory"),
+    (Command::Look, "look"),
+    (Command::History, "history"),
+    (Command::Wait, "wait"),
+    (Command::Start, "start"),
+    (Command::Exit, "exit"),
+];
+
+// arrows, Control, Alt and Enter are the buttons on the original cabinet
+const DEFAULT_BINDINGS: [(Command, &[&str]); 16] = [
+    (Command::MoveUp, &["Up", "k", "NumPad8"]),
+    (Command::MoveDown, &["Down", "j", "NumPad2"]),
+    (Command::MoveLeft, &["Left", "h", "NumPad4"]),
+    (Command::MoveRight, &["Right", "l", "NumPad6"]),
+    (Command::MoveUpLeft, &["y", "NumPad7"]),
+    (Command::MoveUpRight, &["u", "NumPad9"]),
+    (Command::MoveDownLeft, &["b", "NumPad1"]),
+    (Command::MoveDownRight, &["n", "NumPad3"]),
+    (Command::DrinkPotion, &["Control", "q"]),
+    (Command::Equip, &["e"]),
+    (Command::Inventory, &["i"]),
+    (Command::Look, &["x"]),
+    (Command::History, &["m"]),
+    (Command::Wait, &["Alt", ".", "NumPad5"]),
+    (Command::Start, &["Enter", "NumPadEnter"]),
+    (Command::Exit, &["Escape"]),
+];
+
+impl Command {
+    pub fn from_name(name: &str) -> Option<Command> {
+        COMMAND_NAMES.iter().find(|&&(_, n)| n == name).map(|&(command, _)| command)
+    }
+
+    /// The game action this command stands for, or `None` if the front-end handles it itself.
+    pub fn action(self) -> Option<Action> {
+        use self::Command::*;
+        match self {
+            MoveUp => Some(Action::Move(0, -1)),
+            MoveDown => Some(Action::Move(0, 1)),
+            MoveLeft => Some(Action::Move(-1, 0)),
+            MoveRight => Some(Action::Move(1, 0)),
+            MoveUpLeft => Some(Action::Move(-1, -1)),
+            MoveUpRight => Some(Action::Move(1, -1)),
+            MoveDownLeft => Some(Action::Move(-1, 1)),
+            MoveDownRight => Some(Action::Move(1, 1)),
+            DrinkPotion => Some(Action::DrinkPotion),
+            // which item to equip or use is picked from a menu first
+            Equip | Inventory => None,
+            // looking around or reading old messages doesn't change anything
+            Look | History => None,
+            Wait => Some(Action::Wait),
+            Start => Some(Action::Start),
+            Exit => None,
+        }
+    }
+}
+
+/// Maps key names to commands. Keys are named by the character they type (`k`, `.`) or, for
+/// keys that don't type anything, by their name (`Up`, `Enter`, `NumPad8`).
+pub struct KeyBindings {
+    keys: HashMap<String, Command>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let mut bindings = KeyBindings { keys: HashMap::new() };
+        for &(command, keys) in DEFAULT_BINDINGS.iter() {
+            for key in keys {
+                bindings.keys.insert(key.to_string(), command);
+            }
+        }
+        bindings
+    }
+}
+
+impl KeyBindings {
+    /// Loads the defaults, with every command listed in the file at `path` rebound to the keys
+    /// given there, e.g. `drink_potion = ["q", "Control"]`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let config: BTreeMap<String, Vec<String>> = read_toml(path)?;
+
+        let mut bindings = KeyBindings::default();
+        for (name, keys) in config {
+            match Command::from_name(&name) {
+                Some(command) => bindings.bind(command, &keys),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown command `{}`", name))),
+            }
+        }
+        Ok(bindings)
+    }
+
+    /// Replaces all of `command`'s keys. Keys that were bound to something else are taken over.
+    pub fn bind(&mut self, command: Command, keys: &[String]) {
+        self.keys.retain(|_, &mut c| c != command);
+        for key in keys {
+            self.keys.insert(key.clone(), command);
+        }
+    }
+
+    pub fn command(&self, key_name: &str) -> Option<Command> {
+        self.keys.get(key_name).cloned()
+    }
+
+    /// The names of the keys bound to `command`, sorted, for telling the player what to press.
+    pub fn keys(&self, command: Command) -> Vec<&str> {
+        let mut keys: Vec<&str> = self.keys.iter()
+            .filter(|&(_, &c)| c == command)
+            .map(|(key, _)| key.as_str())
+            .collect();
+        keys.sort();
+        keys
+    }
+}
+
+/// The name `key` goes by in the bindings file: the character it types if it's printable,
+/// otherwise its key code, e.g. `k`, `Up` or `NumPad8`.
+pub fn key_name(key: Key) -> String {
+    match key.code {
+        KeyCode::Char => key.printable.to_string(),
+        code => format!("{:?}", code),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{ErrorKind, Write};
+
+    use super::*;
+
+    fn key(code: KeyCode, printable: char) -> Key {
+        Key { code, printable, pressed: true, ..Key::default() }
+    }
+
+    /// Loads bindings from a file holding `contents`, named after the test.
+    fn load(name: &str, contents: &str) -> io::Result<KeyBindings> {
+        let path = env::temp_dir().join(format!("giftrogue-{}-{}.toml", name, ::std::process::id()));
+        File::create(&path)?.write_all(contents.as_bytes())?;
+        let bindings = KeyBindings::load(&path);
+        let _ = fs::remove_file(&path);
+        bindings
+    }
+
+    #[test]
+    fn keys_are_named_by_what_they_type_or_their_code() {
+        assert_eq!(key_name(key(KeyCode::Char, 'k')), "k");
+        assert_eq!(key_name(key(KeyCode::Char, '.')), ".");
+        assert_eq!(key_name(key(KeyCode::Up, '\0')), "Up");
+        assert_eq!(key_name(key(KeyCode::NumPad8, '\0')), "NumPad8");
+        assert_eq!(key_name(key(KeyCode::Enter, '\r')), "Enter");
+    }
+
+    #[test]
+    fn default_bindings_know_the_cabinet_buttons() {
+        let bindings = KeyBindings::default();
+        assert_eq!(bindings.command(&key_name(key(KeyCode::Up, '\0'))), Some(Command::MoveUp));
+        assert_eq!(bindings.command(&key_name(key(KeyCode::Enter, '\r'))), Some(Command::Start));
+        assert_eq!(bindings.command(&key_name(key(KeyCode::Char, 'k'))), Some(Command::MoveUp));
+        assert_eq!(bindings.command("F12"), None);
+        assert_eq!(bindings.keys(Command::Start), vec!["Enter", "NumPadEnter"]);
+    }
+
+    #[test]
+    fn rebinding_a_command_replaces_all_its_keys() {
+        let bindings = load("rebind", "drink_potion = [\"p\", \"Tab\"]\nwait = [\"k\"]").unwrap();
+        assert_eq!(bindings.command("p"), Some(Command::DrinkPotion));
+        assert_eq!(bindings.command("Tab"), Some(Command::DrinkPotion));
+        assert_eq!(bindings.command("q"), None);
+        // `k` is taken over from moving up, which keeps its other keys
+        assert_eq!(bindings.command("k"), Some(Command::Wait));
+        assert_eq!(bindings.command("Up"), Some(Command::MoveUp));
+        assert_eq!(bindings.keys(Command::DrinkPotion), vec!["Tab", "p"]);
+        assert_eq!(bindings.keys(Command::MoveUp), vec!["NumPad8", "Up"]);
+    }
+
+    #[test]
+    fn unknown_commands_and_bad_files_are_rejected() {
+        let unknown = load("unknown", "fly = [\"f\"]").err().unwrap();
+        assert_eq!(unknown.kind(), ErrorKind::InvalidData);
+        assert!(unknown.to_string().contains("fly"));
+        let not_a_list = load("not-a-list", "wait = \"x\"").err().unwrap();
+        assert_eq!(not_a_list.kind(), ErrorKind::InvalidData);
+    }
+}
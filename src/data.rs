@@ -0,0 +1,251 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use tcod::colors::Color;
+use toml;
+
+use ai::{Ai, Behavior, DEFAULT_SIGHT_RADIUS};
+use entity::{spawn_at, Bonus, DeathCallback, Fighter, Glyph, Position};
+use item::{Effect, Equipment, Item, Slot};
+use map::Map;
+use world::{EntityId, World};
+
+pub const DATA_DIR: &str = "data";
+
+const MONSTERS_FILE: &str = "monsters.toml";
+const ITEMS_FILE: &str = "items.toml";
+const PROFILE_FILE: &str = "profile.toml";
+
+/// Everything designers can tweak without touching the code.
+#[derive(Debug)]
+pub struct GameData {
+    pub monsters: Vec<MonsterTemplate>,
+    pub items: Vec<ItemTemplate>,
+    pub profile: Profile,
+}
+
+/// Who the game is for, and the story text written for them.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub intro: String,
+    pub win_text: String,
+    pub reveal_text: String,
+    pub player_color: Color,
+    /// Color of the intro and win text.
+    pub story_color: Color,
+    /// Color of the reveal text and of any message from `win.toml`.
+    pub reveal_color: Color,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MonsterTemplate {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub hp: i32,
+    pub defense: i32,
+    pub power: i32,
+    /// What's left on the floor once it dies.
+    pub corpse: char,
+    /// How likely this monster is to be picked, relative to the others.
+    pub spawn_weight: u32,
+    pub ai: Behavior,
+    /// Below this fraction of its max hp it runs away. Left out, it fights to the death.
+    #[serde(default)]
+    pub flee_below: f32,
+    /// How many tiles away it can see the player from, whether they're lit or not.
+    #[serde(default = "default_sight_radius")]
+    pub sight_radius: i32,
+    /// How much experience killing it is worth.
+    #[serde(default)]
+    pub xp: i32,
+    /// Bosses never spawn at random. One guards the key on the last level instead.
+    #[serde(default)]
+    pub boss: bool,
+    #[serde(default)]
+    pub loot: LootTable,
+}
+
+fn default_sight_radius() -> i32 {
+    DEFAULT_SIGHT_RADIUS
+}
+
+/// What a monster leaves behind when it dies.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LootTable {
+    /// How likely it is to drop one of `items`, from 0 to 1.
+    pub chance: f32,
+    pub items: Vec<LootItem>,
+    /// The least and the most gold it drops.
+    pub gold: (u32, u32),
+    /// Items it always drops, on top of everything else.
+    pub always: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LootItem {
+    /// The name of one of the items in `items.toml`.
+    pub name: String,
+    /// How likely this item is to be dropped, relative to the others in the table.
+    pub weight: u32,
+}
+
+impl MonsterTemplate {
+    pub fn spawn(&self, world: &mut World, map: &mut Map, pos: Position) -> Option<EntityId> {
+        let glyph = Glyph { char: self.glyph, color: self.color };
+        let monster = spawn_at(world, map, pos, glyph, self.name.clone(), true)?;
+        world.fighters.insert(monster, Fighter {
+            max_hp: self.hp,
+            hp: self.hp,
+            defense: self.defense,
+            power: self.power,
+            corpse: self.corpse,
+            on_death: DeathCallback::Monster,
+            bonus: Bonus::default(),
+            xp: self.xp,
+        });
+        world.ais.insert(monster, Ai::new(self.ai, pos.xy(), self.flee_below, self.sight_radius));
+        world.loot.insert(monster, self.loot.clone());
+        Some(monster)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ItemTemplate {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub effect: Effect,
+    pub magnitude: i32,
+    /// How likely this item is to be picked, relative to the others. Items with no weight
+    /// are never scattered around the dungeon.
+    pub spawn_weight: u32,
+    pub stackable: bool,
+    /// Where it's worn, for items with `effect = "equip"`.
+    pub slot: Option<Slot>,
+    #[serde(default)]
+    pub bonus: Bonus,
+    /// What inspecting it says. Without one, it's made up from the effect and magnitude.
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+impl ItemTemplate {
+    pub fn spawn(&self, world: &mut World, map: &mut Map, pos: Position) -> Option<EntityId> {
+        let glyph = Glyph { char: self.glyph, color: self.color };
+        let item = spawn_at(world, map, pos, glyph, self.name.clone(), false)?;
+        world.items.insert(item, Item {
+            effect: self.effect,
+            magnitude: self.magnitude,
+            stackable: self.stackable,
+            count: 1,
+            equipment: self.slot.map(|slot| Equipment { slot, bonus: self.bonus, equipped: false }),
+        });
+        Some(item)
+    }
+}
+
+#[derive(Deserialize)]
+struct MonstersFile {
+    monster: Vec<MonsterTemplate>,
+}
+
+#[derive(Deserialize)]
+struct ItemsFile {
+    item: Vec<ItemTemplate>,
+}
+
+/// The data files that ship with the game, for when there's no data directory to load from.
+impl Default for GameData {
+    fn default() -> Self {
+        let data = GameData {
+            monsters: parse_toml::<MonstersFile, _>(include_str!("../data/monsters.toml"), MONSTERS_FILE)
+                .expect("bundled monsters.toml is invalid").monster,
+            items: parse_toml(include_str!("../data/items.toml"), ITEMS_FILE)
+                .and_then(check_items).expect("bundled items.toml is invalid"),
+            profile: parse_toml(include_str!("../data/profile.toml"), PROFILE_FILE).expect("bundled profile.toml is invalid"),
+        };
+        check_loot(&data).expect("bundled loot tables are invalid");
+        data
+    }
+}
+
+impl GameData {
+    /// The item the player has to bring back to the chest.
+    pub fn key(&self) -> &ItemTemplate {
+        self.items.iter().find(|i| i.effect == Effect::Unlock).expect("validated when loading")
+    }
+
+    /// The item called `name`, if there is one.
+    pub fn item(&self, name: &str) -> Option<&ItemTemplate> {
+        self.items.iter().find(|i| i.name == name)
+    }
+
+    /// Loads every data file from `dir`.
+    pub fn load<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        let data = GameData {
+            monsters: read_toml::<MonstersFile, _>(dir.join(MONSTERS_FILE))?.monster,
+            items: check_items(read_toml(dir.join(ITEMS_FILE))?)?,
+            profile: read_toml(dir.join(PROFILE_FILE))?,
+        };
+        check_loot(&data)?;
+        Ok(data)
+    }
+}
+
+fn read_file<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let mut contents = String::new();
+    File::open(path)?.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+/// Reads the TOML file at `path` into a `T`. Contents that don't fit `T` are `InvalidData`
+/// errors naming the file.
+pub fn read_toml<T: DeserializeOwned, P: AsRef<Path>>(path: P) -> io::Result<T> {
+    let path = path.as_ref();
+    parse_toml(&read_file(path)?, path)
+}
+
+fn parse_toml<T: DeserializeOwned, P: AsRef<Path>>(contents: &str, name: P) -> io::Result<T> {
+    toml::from_str(contents)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", name.as_ref().display(), e)))
+}
+
+/// Makes sure the items make sense together, e.g. that one of them opens the chest.
+fn check_items(file: ItemsFile) -> io::Result<Vec<ItemTemplate>> {
+    for item in &file.item {
+        if (item.effect == Effect::Equip) != item.slot.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("{}: {} needs both effect = \"equip\" and a slot, or neither", ITEMS_FILE, item.name)));
+        }
+    }
+    if !file.item.iter().any(|i| i.effect == Effect::Unlock) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("{}: there's no item with effect = \"unlock\", so the chest can't be opened", ITEMS_FILE)));
+    }
+    Ok(file.item)
+}
+
+/// Makes sure every item a monster can drop exists.
+fn check_loot(data: &GameData) -> io::Result<()> {
+    for monster in &data.monsters {
+        let loot = &monster.loot;
+        let names = loot.items.iter().map(|i| &i.name).chain(loot.always.iter());
+        for name in names {
+            if data.item(name).is_none() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          format!("{}: {} drops {}, which isn't in {}", MONSTERS_FILE, monster.name, name, ITEMS_FILE)));
+            }
+        }
+        if loot.gold.0 > loot.gold.1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("{}: {} drops at least {} gold but at most {}", MONSTERS_FILE, monster.name, loot.gold.0, loot.gold.1)));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,267 @@
+use std;
+
+use tcod::colors::{self, Color};
+
+use game::{push_message, AttackKind, GameEvent};
+use map::Map;
+use messages::Category;
+use path::find_path;
+use world::{EntityId, World};
+
+#[derive(Clone,Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DeathCallback {
+    Player,
+    Monster,
+}
+
+impl DeathCallback {
+    fn callback(self, world: &mut World, id: EntityId, events: &mut Vec<GameEvent>) {
+        use self::DeathCallback::*;
+        let cb: fn(&mut World, EntityId, &mut Vec<GameEvent>) = match self {
+            Player => player_death,
+            Monster => monster_death,
+        };
+        cb(world, id, events);
+    }
+}
+
+fn player_death(world: &mut World, player: EntityId, events: &mut Vec<GameEvent>) {
+    events.push(GameEvent::Died { name: world.name(player).to_string(), is_player: true });
+    let corpse = world.fighters.get(player).map_or('%', |f| f.corpse);
+    if let Some(glyph) = world.glyphs.get_mut(player) {
+        glyph.char = corpse;
+        glyph.color = colors::DARK_RED;
+    }
+}
+
+/// What's left of a monster is just its corpse, and the loot it hasn't dropped yet.
+fn monster_death(world: &mut World, monster: EntityId, events: &mut Vec<GameEvent>) {
+    events.push(GameEvent::Died { name: world.name(monster).to_string(), is_player: false });
+    if let Some(f) = world.fighters.remove(monster) {
+        if let Some(glyph) = world.glyphs.get_mut(monster) {
+            glyph.char = f.corpse;
+        }
+    }
+    world.blocks.remove(monster);
+    world.ais.remove(monster);
+    world.paths.remove(monster);
+}
+
+/// Stats added on top of a fighter's own, e.g. by what they're wearing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bonus {
+    pub max_hp: i32,
+    pub defense: i32,
+    pub power: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fighter {
+    pub max_hp: i32,
+    pub hp: i32,
+    pub defense: i32,
+    pub power: i32,
+    /// The glyph left behind on death.
+    pub corpse: char,
+    pub on_death: DeathCallback,
+    pub bonus: Bonus,
+    /// What a monster is worth when killed, or what the player has earned so far.
+    pub xp: i32,
+}
+
+impl Fighter {
+    pub fn max_hp(&self) -> i32 {
+        self.max_hp + self.bonus.max_hp
+    }
+
+    pub fn defense(&self) -> i32 {
+        self.defense + self.bonus.defense
+    }
+
+    pub fn power(&self) -> i32 {
+        self.power + self.bonus.power
+    }
+}
+
+/// Where an entity is: a tile on the level at `depth`, counting from 0.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+    pub depth: usize,
+}
+
+impl Position {
+    pub fn xy(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+}
+
+/// How an entity is drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Glyph {
+    pub char: char,
+    pub color: Color,
+}
+
+/// Nothing else can stand where an entity with this is.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Blocks;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Chest;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Stairs {
+    Up,
+    Down,
+}
+
+/// Puts a new entity on the tile at `pos`, unless something is in the way there. Entities that
+/// block mark their tile on `map`, which has to be the map of the level at `pos.depth`.
+pub fn spawn_at<S: Into<String>>(world: &mut World, map: &mut Map, pos: Position, glyph: Glyph, name: S, blocks: bool) -> Option<EntityId> {
+    if map.get(pos.x, pos.y).blocks_movement {
+        return None;
+    }
+    let id = world.spawn();
+    world.set_position(id, pos);
+    world.glyphs.insert(id, glyph);
+    world.names.insert(id, name.into());
+    if blocks {
+        map.get_mut(pos.x, pos.y).blocks_movement = true;
+        world.blocks.insert(id, Blocks);
+    }
+    Some(id)
+}
+
+/// Takes one step along a path to `target`, reusing the path from earlier turns when it
+/// still leads there. `soft_obstacles` are tiles other monsters stand on.
+pub fn step_towards(world: &mut World, id: EntityId, target: (i32, i32), map: &mut Map, soft_obstacles: &[(i32, i32)], diagonal: bool) {
+    let pos = match world.position(id) {
+        Some(pos) => pos.xy(),
+        None => return,
+    };
+    let distance = (target.0 - pos.0).abs() + (target.1 - pos.1).abs();
+    let reusable = match world.paths.get_mut(id) {
+        Some(path) => {
+            path.retarget(target)
+                && path.len() as i32 <= distance * 2 + 2
+                && path.next().is_some_and(|(x, y)| !map.get(x, y).blocks_movement)
+        },
+        None => false,
+    };
+    if !reusable {
+        match find_path(map, pos, target, soft_obstacles, diagonal) {
+            Some(path) => world.paths.insert(id, path),
+            None => {
+                world.paths.remove(id);
+            },
+        }
+    }
+
+    let next = world.paths.get(id).and_then(|path| path.next());
+    if let Some((x, y)) = next {
+        if !map.get(x, y).blocks_movement {
+            move_by(world, id, x - pos.0, y - pos.1, map);
+            if let Some(path) = world.paths.get_mut(id) {
+                path.advance();
+            }
+        }
+    }
+}
+
+/// Whether `other` is close enough for `id` to attack. Diagonal neighbours only count with
+/// `diagonal` set and if there's no wall corner between the two.
+pub fn next_to(world: &World, id: EntityId, other: EntityId, map: &Map, diagonal: bool) -> bool {
+    let (pos, other) = match (world.position(id), world.position(other)) {
+        (Some(pos), Some(other)) if pos.depth == other.depth => (pos, other),
+        _ => return false,
+    };
+    let (dx, dy) = (other.x - pos.x, other.y - pos.y);
+    match (dx.abs(), dy.abs()) {
+        (0, 0) | (0, 1) | (1, 0) => true,
+        (1, 1) => diagonal && !map.cuts_corner(pos.x, pos.y, dx, dy),
+        _ => false,
+    }
+}
+
+pub fn move_by(world: &mut World, id: EntityId, dx: i32, dy: i32, map: &mut Map) {
+    let blocks = world.blocks.has(id);
+    if let Some(&pos) = world.position(id) {
+        let to = Position { x: pos.x + dx, y: pos.y + dy, ..pos };
+        if !map.get(to.x, to.y).blocks_movement {
+            // only the blocking changes, the player still remembers having seen both tiles
+            if blocks {
+                map.get_mut(pos.x, pos.y).blocks_movement = false;
+                map.get_mut(to.x, to.y).blocks_movement = true;
+            }
+            world.set_position(id, to);
+        }
+    }
+}
+
+/// Moves the player, or has them attack whatever is in the way. Returns the monster they
+/// attacked if it survived. Bumping into the chest is up to the game.
+pub fn move_or_attack(world: &mut World, player: EntityId, dx: i32, dy: i32, map: &mut Map, events: &mut Vec<GameEvent>) -> Option<EntityId> {
+    let pos = match world.position(player) {
+        Some(&pos) => pos,
+        None => return None,
+    };
+    let (x, y) = (pos.x + dx, pos.y + dy);
+    if !map.get(x, y).blocks_movement {
+        move_by(world, player, dx, dy, map);
+        return None;
+    }
+
+    let there = world.at(pos.depth, (x, y));
+    if let Some(&enemy) = there.iter().find(|&&id| world.fighters.has(id)) {
+        attack(world, player, enemy, true, events);
+        if !world.blocks.has(enemy) {
+            map.get_mut(x, y).blocks_movement = false;
+        }
+        return if world.fighters.has(enemy) { Some(enemy) } else { None };
+    }
+    if there.is_empty() {
+        push_message(events, "You try to attack... the wall?", Category::System);
+    }
+    None
+}
+
+/// Returns the experience the entity was worth if this killed it.
+pub fn take_damage(world: &mut World, id: EntityId, damage: i32, events: &mut Vec<GameEvent>) -> Option<i32> {
+    let killed = match world.fighters.get_mut(id) {
+        Some(f) => {
+            f.hp -= damage;
+            f.hp = std::cmp::min(f.hp, f.max_hp());
+            if f.hp <= 0 { Some(*f) } else { None }
+        },
+        None => None,
+    };
+    killed.map(|f| {
+        f.on_death.callback(world, id, events);
+        f.xp
+    })
+}
+
+pub fn attack(world: &mut World, attacker: EntityId, target: EntityId, by_player: bool, events: &mut Vec<GameEvent>) {
+    let damage = world.fighters.get(attacker).map_or(0, |f| f.power()) - world.fighters.get(target).map_or(0, |f| f.defense());
+    events.push(GameEvent::Attacked {
+        attacker: world.name(attacker).to_string(),
+        target: world.name(target).to_string(),
+        damage: std::cmp::max(damage, 0),
+        by_player,
+        kind: AttackKind::Melee,
+    });
+    if damage > 0 {
+        let from = world.position(attacker).map(Position::xy);
+        if let (Some(ai), Some(from)) = (world.ais.get_mut(target), from) {
+            ai.alert(from);
+        }
+        if let Some(xp) = take_damage(world, target, damage, events) {
+            if let Some(f) = world.fighters.get_mut(attacker) {
+                f.xp += xp;
+            }
+        }
+    }
+}
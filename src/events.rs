@@ -0,0 +1,18 @@
+use game::{GameEvent, GameState};
+
+/// Something that follows what happens in the game, like the message log, statistics or sound
+/// effects. Each one is handed every event a `GameState::step` produced, in order, and picks
+/// out the ones it cares about on its own.
+pub trait Subscriber {
+    /// Called once per event, with `state` as it is after the step that produced it.
+    fn notify(&mut self, event: &GameEvent, state: &GameState);
+}
+
+/// Hands each of `events` to every one of `subscribers`, before moving on to the next event.
+pub fn publish(events: &[GameEvent], state: &GameState, subscribers: &mut [&mut dyn Subscriber]) {
+    for event in events {
+        for subscriber in subscribers.iter_mut() {
+            subscriber.notify(event, state);
+        }
+    }
+}
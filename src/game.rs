@@ -0,0 +1,1087 @@
+use std::rc::Rc;
+
+use tcod::colors;
+use tcod::map::{Map as FovMap, FovAlgorithm};
+use rand::{Rng, SeedableRng};
+use rand::isaac::Isaac64Rng;
+
+use ai::{Ai, AiState, Noise, COMBAT_NOISE};
+use data::{GameData, ItemTemplate};
+use entity::*;
+use item::{self, with_article, Effect, Inventory};
+use map::*;
+use messages::Category;
+use options::GameOptions;
+use world::{EntityId, World};
+use {SCREEN_WIDTH, SCREEN_HEIGHT, MAP_WIDTH, MAP_HEIGHT, TORCH_RADIUS};
+
+const FOV_ALGO: FovAlgorithm = FovAlgorithm::Shadow;
+const FOV_LIGHT_WALLS: bool = true;
+
+const MAX_ROOM_MONSTERS: i32 = 3;
+const MAX_ROOM_ITEMS: i32 = 1;
+
+/// How many levels the dungeon has. The key is always on the last one.
+pub const DUNGEON_DEPTH: usize = 3;
+
+/// Experience needed to go from level 1 to 2. Every level after that takes `LEVEL_UP_FACTOR` more.
+const LEVEL_UP_BASE: i32 = 200;
+const LEVEL_UP_FACTOR: i32 = 150;
+
+/// How many tiles a thrown item flies before it drops.
+const THROW_RANGE: i32 = 6;
+/// Damage a thrown item does before its power bonus, if it has one.
+const THROW_DAMAGE: i32 = 1;
+/// How far away something being thrown can be heard when it hits.
+const THROW_NOISE: i32 = 6;
+
+/// How close a monster has to be for a lightning bolt to reach it.
+const LIGHTNING_RANGE: i32 = 5;
+/// How far from where it's aimed a fireball burns.
+const FIREBALL_RADIUS: i32 = 2;
+
+/// Every random decision in a run is drawn from a single generator, so a seed fully
+/// determines the run. Isaac64 is used because it behaves the same on 32 and 64 bit targets.
+pub type GameRng = Isaac64Rng;
+
+pub fn new_rng(seed: u64) -> GameRng {
+    Isaac64Rng::from_seed(&[seed])
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GameStage {
+    Title,
+    Playing,
+    GameOver,
+    Won,
+}
+
+/// A single player input, independent of whichever front-end produced it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Action {
+    /// Step to the neighbouring tile in the direction `(dx, dy)`, or attack whatever is there.
+    Move(i32, i32),
+    DrinkPotion,
+    /// Put on the item in this inventory slot, or take it off if it's already on.
+    Equip(usize),
+    /// Use the item in this inventory slot: drink or read it, or put it on or take it off.
+    UseItem(usize),
+    /// Read the scroll in this inventory slot at the tile `(x, y)`, which has to be in sight.
+    UseItemAt(usize, i32, i32),
+    /// Put the item in this inventory slot down on the floor.
+    DropItem(usize),
+    /// Throw one of the items in this inventory slot in the direction `(dx, dy)`.
+    ThrowItem(usize, i32, i32),
+    /// Spend a level up on raising this stat. Doesn't take a turn.
+    LevelUp(Stat),
+    Wait,
+    /// Start a game from the title screen, restart after dying or reveal the gift after winning.
+    Start,
+}
+
+/// The stats a level up can raise.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Stat {
+    MaxHp,
+    Power,
+    Defense,
+}
+
+/// What an attack was made with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttackKind {
+    Melee,
+    /// The player threw the item with this name.
+    Thrown(String),
+    Lightning,
+    Fireball,
+}
+
+/// Everything a front-end needs to know about what happened during a `GameState::step`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameEvent {
+    Message(String, Category),
+    ClearMessages,
+    StageChanged(GameStage),
+    /// The player's field of view changed, so the map needs to be redrawn.
+    FovRecomputed,
+    /// The whole state was replaced by a freshly generated game.
+    NewGame,
+    /// The player took the stairs to the level at this depth, counting from 0.
+    LevelChanged(usize),
+    /// The player has won and asked to open their gift.
+    RevealGift,
+    /// `attacker` hit `target` with `kind` for `damage` hp. No damage means the blow had no
+    /// effect. `by_player` is whether the player dealt it to something else, so the player
+    /// catching their own fireball counts as damage taken.
+    Attacked { attacker: String, target: String, damage: i32, by_player: bool, kind: AttackKind },
+    /// `name` was killed.
+    Died { name: String, is_player: bool },
+    /// The player picked up the item with this name.
+    PickedUp(String),
+    PickedUpGold(u32),
+    /// The player's pack had no room for the item with this name.
+    PackFull(String),
+    /// `monster` dropped `item` where it died.
+    LootDropped { monster: String, item: String },
+    GoldDropped { monster: String, amount: u32 },
+    /// The player threw the item with this name and it didn't hit anything.
+    Thrown(String),
+    /// The potion with this name broke where it landed.
+    Shattered(String),
+    /// The player put the item with this name down.
+    Dropped(String),
+    /// The player put on the item with this name.
+    Equipped(String),
+    /// The player took off the item with this name.
+    Unequipped(String),
+    /// The player's fireball went off.
+    FireballExploded,
+    /// The monster with this name was confused by the player.
+    Confused(String),
+    /// The monster with this name came to its senses.
+    ConfusionEnded(String),
+    /// Something the player used raised this stat for good.
+    StatRaised(Stat),
+    /// The player used up one `item` and has `left` of them.
+    ItemUsed { item: String, left: u32 },
+    /// The player tried to open the chest without the key.
+    ChestLocked,
+    /// The player opened the chest, which wins the game.
+    ChestOpened,
+    /// The player reached this experience level.
+    LeveledUp(i32),
+}
+
+/// Picks one of `items` at random, with chances proportional to `weight`.
+pub fn pick_weighted<'a, T, F: Fn(&T) -> u32>(rng: &mut GameRng, items: &'a [T], weight: F) -> Option<&'a T> {
+    let total: u32 = items.iter().map(&weight).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut roll = rng.gen_range(0, total);
+    items.iter().find(|item| {
+        let w = weight(item);
+        if roll < w {
+            true
+        } else {
+            roll -= w;
+            false
+        }
+    })
+}
+
+pub fn push_message<T: Into<String>>(events: &mut Vec<GameEvent>, message: T, category: Category) {
+    events.push(GameEvent::Message(message.into(), category));
+}
+
+/// One floor of the dungeon. Levels the player has left keep everything as it was, including
+/// which tiles they've explored. What's on a level lives in the `World`, with its `depth`.
+#[derive(Serialize, Deserialize)]
+pub struct Level {
+    pub map: Map,
+    /// Where the player arrives from the level above.
+    pub entrance: (i32, i32),
+    /// Where the player arrives from the level below.
+    pub exit: Option<(i32, i32)>,
+    /// Noises made since the monsters last had a chance to hear them.
+    pub noises: Vec<Noise>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GameState {
+    /// Every entity on every level, the player and what they carry included.
+    pub world: World,
+    pub player: EntityId,
+    pub levels: Vec<Level>,
+    /// Index into `levels` of the level the player is on.
+    pub depth: usize,
+    pub camera_pos: (i32, i32),
+    /// Derived from the current level's map, so it's rebuilt with `rebuild_fov` instead of being saved.
+    #[serde(skip, default = "blank_fov_map")]
+    pub fov_map: FovMap,
+    pub prev_player_pos: (i32, i32),
+    pub stage: GameStage,
+    /// The monster the player last fought, for as long as it's alive and in sight.
+    pub recent_enemy: Option<EntityId>,
+    pub inventory: Inventory,
+    pub gold: u32,
+    /// The player's experience level, starting at 1.
+    pub player_level: i32,
+    /// How many turns the player has taken.
+    pub turn: u32,
+    pub seed: u64,
+    pub options: GameOptions,
+    /// Isaac64 can't be serialized, so saves store a fresh seed for it instead.
+    #[serde(skip, default = "unseeded_rng")]
+    pub rng: GameRng,
+    /// Content isn't part of a save, it's reattached when the save is loaded.
+    #[serde(skip)]
+    pub data: Rc<GameData>,
+}
+
+fn blank_fov_map() -> FovMap {
+    FovMap::new(MAP_WIDTH, MAP_HEIGHT)
+}
+
+fn unseeded_rng() -> GameRng {
+    new_rng(0)
+}
+
+impl GameState {
+    pub fn new(seed: u64, options: GameOptions, data: Rc<GameData>) -> Self {
+        let mut rng = new_rng(seed);
+        let mut world = World::default();
+        let mut levels: Vec<Level> = (0..DUNGEON_DEPTH).map(|depth| generate_level(depth, &data, &mut world, &mut rng)).collect();
+
+        let (px, py) = levels[0].entrance;
+        let glyph = Glyph { char: '@', color: data.profile.player_color };
+        let player = spawn_at(&mut world, &mut levels[0].map, Position { x: px, y: py, depth: 0 }, glyph, data.profile.name.clone(), true).unwrap();
+        world.fighters.insert(player, Fighter {
+            max_hp: 30,
+            hp: 30,
+            defense: 2,
+            power: 5,
+            corpse: '%',
+            on_death: DeathCallback::Player,
+            bonus: Bonus::default(),
+            xp: 0,
+        });
+
+        let mut state = GameState {
+            world,
+            player,
+            levels,
+            depth: 0,
+            camera_pos: (px, py),
+            fov_map: blank_fov_map(),
+            prev_player_pos: (px, py),
+            stage: GameStage::Title,
+            recent_enemy: None,
+            inventory: Inventory::default(),
+            gold: 0,
+            player_level: 1,
+            turn: 0,
+            seed,
+            options,
+            rng,
+            data,
+        };
+
+        state.rebuild_fov();
+
+        state
+    }
+
+    /// The level the player is on.
+    pub fn level(&self) -> &Level {
+        &self.levels[self.depth]
+    }
+
+    /// Where the player is on the current level.
+    pub fn player_pos(&self) -> (i32, i32) {
+        self.world.position(self.player).map_or((0, 0), Position::xy)
+    }
+
+    /// The closest monster the player can see, if there is one.
+    pub fn closest_visible_npc(&self) -> Option<EntityId> {
+        let (px, py) = self.player_pos();
+        self.world.on_level(self.depth).into_iter()
+            .filter(|&id| self.world.ais.has(id) && self.world.fighters.has(id))
+            .filter_map(|id| self.world.position(id).map(|&pos| (id, pos)))
+            .filter(|&(_, pos)| self.fov_map.is_in_fov(pos.x, pos.y))
+            .min_by_key(|&(_, pos)| (pos.x - px).pow(2) + (pos.y - py).pow(2))
+            .map(|(id, _)| id)
+    }
+
+    /// What the player knows about the tile at `pos` on the current level, a line per thing:
+    /// the tile itself first, then whatever is on it if the player can see it.
+    pub fn look_at(&self, pos: (i32, i32)) -> Vec<String> {
+        let level = self.level();
+        let (x, y) = pos;
+        if !level.map.contains(x, y) || !level.map.get(x, y).explored {
+            return vec!["You haven't seen this place.".to_string()];
+        }
+        let tile = if level.map.get(x, y).is_wall() { "A wall" } else { "The floor" };
+        if !self.fov_map.is_in_fov(x, y) {
+            return vec![format!("{}. You remember it, but can't see it from here.", tile)];
+        }
+
+        let world = &self.world;
+        let mut lines = vec![format!("{}.", tile)];
+        let mut here = world.at(self.depth, pos);
+        // the player first, then monsters, then everything else
+        here.sort_by_key(|&id| (id != self.player, !world.fighters.has(id)));
+        for id in here {
+            lines.push(if id == self.player {
+                let hp = world.fighters.get(id).map_or(0, |f| f.hp);
+                let max_hp = world.fighters.get(id).map_or(0, |f| f.max_hp());
+                format!("You, {}, with {}/{} HP.", world.name(id), hp, max_hp)
+            } else if let Some(amount) = world.gold.get(id) {
+                format!("{} gold.", amount)
+            } else if world.items.has(id) {
+                item::describe(&self.data, world, id)
+            } else if world.chests.has(id) || world.stairs.has(id) {
+                format!("The {}.", world.name(id))
+            } else {
+                describe_npc(world, id)
+            });
+        }
+        lines
+    }
+
+    /// How much experience the player needs for their next level.
+    pub fn xp_to_level_up(&self) -> i32 {
+        LEVEL_UP_BASE + (self.player_level - 1) * LEVEL_UP_FACTOR
+    }
+
+    /// Whether the player has earned a level up they haven't spent yet.
+    pub fn can_level_up(&self) -> bool {
+        self.stage == GameStage::Playing && self.world.fighters.get(self.player).is_some_and(|f| f.xp >= self.xp_to_level_up())
+    }
+
+    /// Tells the player once they've earned a level up. `ready` is whether they already had one
+    /// before their last kill, in which case they've heard about it already.
+    fn announce_level_up(&self, ready: bool, events: &mut Vec<GameEvent>) {
+        if !ready && self.can_level_up() {
+            push_message(events, "Your battle skills grow stronger!", Category::System);
+        }
+    }
+
+    /// Copies the current level's map into `fov_map` and recomputes what the player can see.
+    pub fn rebuild_fov(&mut self) {
+        {
+            let map = &self.levels[self.depth].map;
+            for y in 0..MAP_HEIGHT-1 {
+                for x in 0..MAP_WIDTH {
+                    self.fov_map.set(x, y,
+                        !map.get(x, y).blocks_sight,
+                        !map.get(x, y).blocks_movement
+                    );
+                }
+            }
+        }
+        compute_fov(self, true);
+    }
+
+    /// Advances the simulation by one player action. Actions that make no sense in the
+    /// current stage are ignored and produce no events.
+    pub fn step(&mut self, action: Action) -> Vec<GameEvent> {
+        use self::Action::*;
+        use self::GameStage::*;
+
+        let mut events = Vec::new();
+        let from = self.player_pos();
+        match (action, self.stage) {
+            // only ever a single step, anything else would skip over what's in between
+            (Move(dx, dy), Playing) if dx.abs() > 1 || dy.abs() > 1 || (dx, dy) == (0, 0) => {},
+            (Move(dx, dy), Playing) if dx != 0 && dy != 0 && !self.options.diagonal_movement => {},
+            (Move(dx, dy), Playing) if self.level().map.cuts_corner(from.0, from.1, dx, dy) => {
+                push_message(&mut events, "You can't squeeze past the corner.", Category::System);
+            },
+            (Move(dx, dy), Playing) => {
+                let to = (from.0 + dx, from.1 + dy);
+                let chest = self.world.at(self.depth, to).into_iter().any(|id| self.world.chests.has(id));
+                let enemy = if chest {
+                    if self.inventory.has(&self.world, Effect::Unlock) {
+                        open_chest(&mut events, &mut self.stage);
+                    } else {
+                        events.push(GameEvent::ChestLocked);
+                    }
+                    None
+                } else {
+                    let ready = self.can_level_up();
+                    let enemy = {
+                        let world = &mut self.world;
+                        let level = &mut self.levels[self.depth];
+                        if world.at(self.depth, to).into_iter().any(|id| world.fighters.has(id)) {
+                            level.noises.push(Noise { pos: to, loudness: COMBAT_NOISE });
+                        }
+                        move_or_attack(world, self.player, dx, dy, &mut level.map, &mut events)
+                    };
+                    self.announce_level_up(ready, &mut events);
+                    enemy
+                };
+                if let Some(enemy) = enemy {
+                    self.recent_enemy = Some(enemy);
+                }
+                if from != self.player_pos() {
+                    self.take_stairs(&mut events);
+                }
+                self.take_turn(&mut events);
+            },
+            (DrinkPotion, Playing) => {
+                match self.inventory.first_potion(&self.world) {
+                    Some(index) => self.use_item(index, None, &mut events),
+                    None => push_message(&mut events, "No potions left!", Category::System),
+                }
+                self.take_turn(&mut events);
+            },
+            (Equip(index), Playing) if index < self.inventory.items.len() => {
+                self.toggle_equipment(index, &mut events);
+                self.take_turn(&mut events);
+            },
+            (UseItem(index), Playing) if index < self.inventory.items.len() => {
+                let id = self.inventory.items[index];
+                match self.world.items.get(id).map(|i| i.effect) {
+                    Some(Effect::Equip) => self.toggle_equipment(index, &mut events),
+                    Some(effect) if effect.is_consumable() => self.use_item(index, None, &mut events),
+                    _ => {
+                        let name = with_article(self.world.name(id));
+                        push_message(&mut events, format!("You can't use {} here.", name), Category::System);
+                    },
+                }
+                self.take_turn(&mut events);
+            },
+            (UseItemAt(index, x, y), Playing) if index < self.inventory.items.len() => {
+                self.use_item(index, Some((x, y)), &mut events);
+                self.take_turn(&mut events);
+            },
+            (DropItem(index), Playing) if index < self.inventory.items.len() => {
+                self.drop_item(index, &mut events);
+                self.take_turn(&mut events);
+            },
+            (ThrowItem(_, dx, dy), Playing) if dx != 0 && dy != 0 && !self.options.diagonal_movement => {
+                push_message(&mut events, "You can only throw straight without diagonal movement.", Category::System);
+            },
+            (ThrowItem(index, dx, dy), Playing) if index < self.inventory.items.len() && (dx, dy) != (0, 0) => {
+                self.throw_item(index, dx.signum(), dy.signum(), &mut events);
+                self.take_turn(&mut events);
+            },
+            (LevelUp(stat), Playing) if self.can_level_up() => {
+                self.level_up(stat, &mut events);
+            },
+            (Wait, Playing) => {
+                push_message(&mut events, "Waited a turn.", Category::System);
+                self.take_turn(&mut events);
+            },
+            (Start, Title) => {
+                self.stage = Playing;
+                events.push(GameEvent::ClearMessages);
+                events.push(GameEvent::StageChanged(Playing));
+            },
+            (Start, GameOver) => {
+                // the next run's seed comes from this one, so a whole session replays from one seed
+                let seed = self.rng.gen();
+                *self = GameState::new(seed, self.options, self.data.clone());
+                events.push(GameEvent::NewGame);
+                events.push(GameEvent::ClearMessages);
+                events.push(GameEvent::StageChanged(Title));
+                push_message(&mut events, format!("New Game Started! {}", self.data.profile.intro), Category::Story);
+            },
+            (Start, Won) => {
+                events.push(GameEvent::RevealGift);
+            },
+            _ => {},
+        }
+        events
+    }
+
+    /// Lets the rest of the world react to the player's turn.
+    fn take_turn(&mut self, events: &mut Vec<GameEvent>) {
+        self.turn += 1;
+        self.drop_loot(events);
+        handle_camera(self);
+        if compute_fov(self, false) {
+            events.push(GameEvent::FovRecomputed);
+        }
+        // noises made while the monsters act are only heard by all of them next turn
+        let heard = self.level().noises.len();
+        for id in self.world.on_level(self.depth) {
+            if self.world.ais.has(id) {
+                Ai::take_turn(id, self, events);
+            }
+        }
+        self.levels[self.depth].noises.drain(..heard);
+        // standing still doesn't pick up again what was just dropped
+        let pos = self.player_pos();
+        if self.prev_player_pos != pos {
+            self.pick_up_items(events);
+        }
+        self.prev_player_pos = pos;
+
+        let alive = self.world.fighters.get(self.player).is_some_and(|f| f.hp > 0);
+        if !alive && self.stage == GameStage::Playing {
+            self.stage = GameStage::GameOver;
+            events.push(GameEvent::StageChanged(GameStage::GameOver));
+        }
+    }
+
+    /// Moves the player to the next level if they just stepped onto stairs.
+    fn take_stairs(&mut self, events: &mut Vec<GameEvent>) {
+        let (x, y) = self.player_pos();
+        let stairs = self.world.at(self.depth, (x, y)).into_iter()
+            .filter_map(|id| self.world.stairs.get(id).cloned())
+            .next();
+        let (depth, arrival) = match stairs {
+            Some(Stairs::Down) if self.depth + 1 < self.levels.len() => (self.depth + 1, self.levels[self.depth + 1].entrance),
+            Some(Stairs::Up) if self.depth > 0 => match self.levels[self.depth - 1].exit {
+                Some(exit) => (self.depth - 1, exit),
+                None => return,
+            },
+            _ => return,
+        };
+
+        self.levels[self.depth].map.get_mut(x, y).blocks_movement = false;
+        self.levels[self.depth].noises.clear();
+        // next to the stairs rather than on them, so stepping back onto them takes them again
+        let (x, y) = free_spot_beside(&self.levels[depth].map, arrival);
+        self.levels[depth].map.get_mut(x, y).blocks_movement = true;
+        let going_down = depth > self.depth;
+        self.depth = depth;
+        self.world.set_position(self.player, Position { x, y, depth });
+        self.camera_pos = (x, y);
+        self.recent_enemy = None;
+        self.rebuild_fov();
+
+        events.push(GameEvent::LevelChanged(depth));
+        events.push(GameEvent::FovRecomputed);
+        let msg = if going_down { "You descend the stairs" } else { "You climb the stairs" };
+        push_message(events, format!("{} to depth {}.", msg, depth + 1), Category::System);
+    }
+
+    fn pick_up_items(&mut self, events: &mut Vec<GameEvent>) {
+        let pos = self.player_pos();
+        for id in self.world.at(self.depth, pos).into_iter().rev() {
+            if let Some(&amount) = self.world.gold.get(id) {
+                self.world.despawn(id);
+                self.gold += amount;
+                events.push(GameEvent::PickedUpGold(amount));
+            } else if self.world.items.has(id) {
+                let name = self.world.name(id).to_string();
+                match self.inventory.add(&mut self.world, id) {
+                    Ok(()) => events.push(GameEvent::PickedUp(name)),
+                    // it stays where it is
+                    Err(_) => events.push(GameEvent::PackFull(name)),
+                }
+            }
+        }
+    }
+
+    fn level_up(&mut self, stat: Stat, events: &mut Vec<GameEvent>) {
+        let needed = self.xp_to_level_up();
+        self.player_level += 1;
+        if let Some(f) = self.world.fighters.get_mut(self.player) {
+            f.xp -= needed;
+            match stat {
+                Stat::MaxHp => {
+                    f.max_hp += 20;
+                    f.hp += 20;
+                },
+                Stat::Power => f.power += 1,
+                Stat::Defense => f.defense += 1,
+            }
+        }
+        events.push(GameEvent::LeveledUp(self.player_level));
+    }
+
+    /// Drops what the monsters the player killed were carrying where they fell.
+    fn drop_loot(&mut self, events: &mut Vec<GameEvent>) {
+        let data = self.data.clone();
+        let dead: Vec<EntityId> = self.world.loot.iter()
+            .map(|(id, _)| id)
+            .filter(|&id| !self.world.fighters.has(id))
+            .collect();
+        for id in dead {
+            let (loot, pos) = match (self.world.loot.remove(id), self.world.position(id)) {
+                (Some(loot), Some(&pos)) => (loot, pos),
+                _ => continue,
+            };
+            let monster = self.world.name(id).to_string();
+            let map = &mut self.levels[pos.depth].map;
+            let mut drops: Vec<&ItemTemplate> = loot.always.iter().filter_map(|name| data.item(name)).collect();
+            if self.rng.gen::<f32>() < loot.chance {
+                if let Some(entry) = pick_weighted(&mut self.rng, &loot.items, |i| i.weight) {
+                    drops.extend(data.item(&entry.name));
+                }
+            }
+            for template in drops {
+                if template.spawn(&mut self.world, map, pos).is_some() {
+                    events.push(GameEvent::LootDropped { monster: monster.clone(), item: template.name.clone() });
+                }
+            }
+
+            let (least, most) = loot.gold;
+            let amount = if most > 0 { self.rng.gen_range(least, most + 1) } else { 0 };
+            if amount > 0 && gold_pile(&mut self.world, map, pos, amount).is_some() {
+                events.push(GameEvent::GoldDropped { monster: monster.clone(), amount });
+            }
+        }
+    }
+
+    /// Puts the whole stack in inventory slot `index` down where the player is standing.
+    fn drop_item(&mut self, index: usize, events: &mut Vec<GameEvent>) {
+        let id = self.inventory.remove(&mut self.world, index);
+        let (x, y) = self.player_pos();
+        self.world.set_position(id, Position { x, y, depth: self.depth });
+        events.push(GameEvent::Dropped(self.world.name(id).to_string()));
+        self.refresh_bonus();
+    }
+
+    /// Throws one item from inventory slot `index` in the direction `(dx, dy)`. It hits the first
+    /// monster in the way, and lands on the floor in front of whatever stopped it unless it's
+    /// a potion, which shatters.
+    fn throw_item(&mut self, index: usize, dx: i32, dy: i32, events: &mut Vec<GameEvent>) {
+        let id = self.inventory.split_one(&mut self.world, index);
+        self.refresh_bonus();
+        let (mut x, mut y) = self.player_pos();
+        let mut target = None;
+        for _ in 0..THROW_RANGE {
+            let map = &self.level().map;
+            let next = (x + dx, y + dy);
+            if map.cuts_corner(x, y, dx, dy) {
+                break;
+            }
+            target = self.world.at(self.depth, next).into_iter().find(|&id| self.world.fighters.has(id));
+            // anything else in the way, like the chest, stops it without being hit
+            if target.is_some() || map.get(next.0, next.1).blocks_movement {
+                break;
+            }
+            x = next.0;
+            y = next.1;
+        }
+
+        let item = *self.world.items.get(id).expect("only items are kept in the inventory");
+        let name = self.world.name(id).to_string();
+        match target {
+            Some(monster) => {
+                let power = item.equipment.map_or(0, |e| e.bonus.power);
+                let damage = THROW_DAMAGE + power - self.world.fighters.get(monster).map_or(0, |f| f.defense());
+                events.push(GameEvent::Attacked {
+                    attacker: self.world.name(self.player).to_string(),
+                    target: self.world.name(monster).to_string(),
+                    damage: ::std::cmp::max(damage, 0),
+                    by_player: true,
+                    kind: AttackKind::Thrown(name.clone()),
+                });
+                self.levels[self.depth].noises.push(Noise { pos: (x + dx, y + dy), loudness: THROW_NOISE });
+                self.hurt_npc(monster, damage, events);
+            },
+            None => events.push(GameEvent::Thrown(name.clone())),
+        }
+
+        if item.effect.is_potion() {
+            events.push(GameEvent::Shattered(name));
+            self.world.despawn(id);
+        } else {
+            self.world.set_position(id, Position { x, y, depth: self.depth });
+        }
+    }
+
+    /// Deals `damage` to the monster `id`. The player is to blame, so it comes after them and
+    /// they get the experience if it dies.
+    fn hurt_npc(&mut self, id: EntityId, damage: i32, events: &mut Vec<GameEvent>) {
+        let player_pos = self.player_pos();
+        if let Some(ai) = self.world.ais.get_mut(id) {
+            ai.alert(player_pos);
+        }
+        if damage <= 0 {
+            return;
+        }
+        let ready = self.can_level_up();
+        if let Some(xp) = take_damage(&mut self.world, id, damage, events) {
+            if let Some(f) = self.world.fighters.get_mut(self.player) {
+                f.xp += xp;
+            }
+        }
+        self.announce_level_up(ready, events);
+        if !self.world.blocks.has(id) {
+            if let Some(&pos) = self.world.position(id) {
+                self.levels[pos.depth].map.get_mut(pos.x, pos.y).blocks_movement = false;
+            }
+        }
+    }
+
+    /// Reports the player's spell of `kind` hitting `target` for `damage` hp, which may be the
+    /// player themselves. The damage is dealt by the caller.
+    fn spell_hit(&self, target: EntityId, damage: i32, kind: AttackKind, events: &mut Vec<GameEvent>) {
+        events.push(GameEvent::Attacked {
+            attacker: self.world.name(self.player).to_string(),
+            target: self.world.name(target).to_string(),
+            damage,
+            by_player: target != self.player,
+            kind,
+        });
+    }
+
+    /// Casts the spell on a scroll, aimed at `target` if it needs aiming. Returns `false` if
+    /// there was nothing to cast it at, in which case the scroll isn't used up.
+    fn cast(&mut self, effect: Effect, magnitude: i32, target: Option<(i32, i32)>, events: &mut Vec<GameEvent>) -> bool {
+        let target = match target {
+            // the map's bounds go first, the FOV map can't be asked about anything outside them
+            Some((x, y)) if !self.level().map.contains(x, y) || !self.fov_map.is_in_fov(x, y) => {
+                push_message(events, "You can't see there.", Category::System);
+                return false;
+            },
+            None if effect.needs_target() => {
+                push_message(events, "You need to aim it at something.", Category::System);
+                return false;
+            },
+            target => target,
+        };
+        let (px, py) = self.player_pos();
+
+        match (effect, target) {
+            (Effect::Lightning, _) => {
+                let id = self.closest_visible_npc().filter(|&id| {
+                    self.world.position(id).is_some_and(|pos| (pos.x - px).pow(2) + (pos.y - py).pow(2) <= LIGHTNING_RANGE.pow(2))
+                });
+                let id = match id {
+                    Some(id) => id,
+                    None => {
+                        push_message(events, "No enemy is close enough to strike.", Category::System);
+                        return false;
+                    },
+                };
+                let pos = self.world.position(id).map_or((px, py), Position::xy);
+                self.spell_hit(id, magnitude, AttackKind::Lightning, events);
+                self.levels[self.depth].noises.push(Noise { pos, loudness: COMBAT_NOISE });
+                self.hurt_npc(id, magnitude, events);
+                true
+            },
+            (Effect::Fireball, Some((x, y))) => {
+                let in_blast = |ex: i32, ey: i32| (ex - x).pow(2) + (ey - y).pow(2) <= FIREBALL_RADIUS.pow(2);
+                events.push(GameEvent::FireballExploded);
+                let burned: Vec<EntityId> = self.world.on_level(self.depth).into_iter()
+                    .filter(|&id| {
+                        let pos = self.world.position(id).map_or((px, py), Position::xy);
+                        id != self.player && self.world.fighters.has(id) && in_blast(pos.0, pos.1)
+                    })
+                    .collect();
+                for id in burned {
+                    self.spell_hit(id, magnitude, AttackKind::Fireball, events);
+                    self.hurt_npc(id, magnitude, events);
+                }
+                if in_blast(px, py) {
+                    self.spell_hit(self.player, magnitude, AttackKind::Fireball, events);
+                    take_damage(&mut self.world, self.player, magnitude, events);
+                }
+                self.levels[self.depth].noises.push(Noise { pos: (x, y), loudness: COMBAT_NOISE });
+                true
+            },
+            (Effect::Confusion, Some((x, y))) => {
+                let monster = self.world.at(self.depth, (x, y)).into_iter().find(|&id| self.world.ais.has(id));
+                match monster {
+                    Some(monster) => {
+                        if let Some(ai) = self.world.ais.get_mut(monster) {
+                            ai.confused = magnitude;
+                        }
+                        events.push(GameEvent::Confused(self.world.name(monster).to_string()));
+                        true
+                    },
+                    None => {
+                        push_message(events, "There's no enemy there.", Category::System);
+                        false
+                    },
+                }
+            },
+            _ => false,
+        }
+    }
+
+    /// Equips the item in inventory slot `index`, taking off whatever was in its slot, or takes
+    /// it off if it's already equipped.
+    fn toggle_equipment(&mut self, index: usize, events: &mut Vec<GameEvent>) {
+        let id = self.inventory.items[index];
+        let target = match self.world.items.get(id).and_then(|i| i.equipment) {
+            Some(equipment) => equipment,
+            None => {
+                push_message(events, format!("You can't equip {}.", with_article(self.world.name(id))), Category::System);
+                return;
+            },
+        };
+        let worn: Vec<EntityId> = self.inventory.items.iter().cloned()
+            .filter(|&other| {
+                self.world.items.get(other).and_then(|i| i.equipment).is_some_and(|e| e.equipped && e.slot == target.slot)
+            })
+            .collect();
+        for other in worn {
+            set_equipped(&mut self.world, other, false);
+            events.push(GameEvent::Unequipped(self.world.name(other).to_string()));
+        }
+        if !target.equipped {
+            set_equipped(&mut self.world, id, true);
+            events.push(GameEvent::Equipped(self.world.name(id).to_string()));
+        }
+        self.refresh_bonus();
+    }
+
+    /// Recomputes what the player's equipment adds to their stats.
+    fn refresh_bonus(&mut self) {
+        let bonus = self.inventory.bonus(&self.world);
+        if let Some(f) = self.world.fighters.get_mut(self.player) {
+            f.bonus = bonus;
+            f.hp = ::std::cmp::min(f.hp, f.max_hp());
+        }
+    }
+
+    /// Uses up one of the items in inventory slot `index`, on the player or, for scrolls, on
+    /// `target` or whatever the spell finds.
+    fn use_item(&mut self, index: usize, target: Option<(i32, i32)>, events: &mut Vec<GameEvent>) {
+        let id = self.inventory.items[index];
+        let (item, name) = match self.world.items.get(id) {
+            Some(&item) => (item, self.world.name(id).to_string()),
+            None => return,
+        };
+        let used = if item.effect.is_spell() {
+            self.cast(item.effect, item.magnitude, target, events)
+        } else {
+            item.effect.apply(item.magnitude, &mut self.world, self.player, events)
+        };
+        if used {
+            self.inventory.take_one(&mut self.world, index);
+            events.push(GameEvent::ItemUsed { item: name, left: item.count - 1 });
+        } else if !item.effect.is_spell() {
+            push_message(events, format!("The {} has no effect.", name), Category::System);
+        }
+    }
+}
+
+fn set_equipped(world: &mut World, id: EntityId, equipped: bool) {
+    if let Some(equipment) = world.items.get_mut(id).and_then(|i| i.equipment.as_mut()) {
+        equipment.equipped = equipped;
+    }
+}
+
+/// A monster's name, health and what it's up to.
+fn describe_npc(world: &World, id: EntityId) -> String {
+    let (ai, fighter) = match (world.ais.get(id), world.fighters.get(id)) {
+        (Some(&ai), Some(&fighter)) => (ai, fighter),
+        _ => return format!("The remains of {}.", with_article(world.name(id))),
+    };
+    let mood = if ai.confused > 0 {
+        "confused"
+    } else {
+        match ai.state {
+            AiState::Asleep => "hostile, but asleep",
+            AiState::Fleeing => "hostile, but running away",
+            AiState::Hunting { .. } => "hostile and hunting",
+            _ => "hostile",
+        }
+    };
+    format!("{}: {}/{} HP, {}.", world.name(id), fighter.hp, fighter.max_hp(), mood)
+}
+
+fn open_chest(events: &mut Vec<GameEvent>, stage: &mut GameStage) {
+    events.push(GameEvent::ChestOpened);
+    *stage = GameStage::Won;
+    events.push(GameEvent::StageChanged(GameStage::Won));
+}
+
+/// Finds the closest tile around `pos`, but not `pos` itself, that nothing is standing on.
+/// Straight neighbours come before diagonal ones, which can't be reached without diagonal
+/// movement. Falls back to `pos` if there's no room at all.
+fn free_spot_beside(map: &Map, pos: (i32, i32)) -> (i32, i32) {
+    let mut offsets: Vec<(i32, i32)> = (-2..3)
+        .flat_map(|dy| (-2..3).map(move |dx| (dx, dy)))
+        .filter(|&offset| offset != (0, 0))
+        .collect();
+    offsets.sort_by_key(|&(dx, dy)| dx * dx + dy * dy);
+    offsets.into_iter()
+        .map(|(dx, dy)| (pos.0 + dx, pos.1 + dy))
+        .find(|&(x, y)| !map.get(x, y).blocks_movement)
+        .unwrap_or(pos)
+}
+
+/// Generates the level at `depth`. The first level has the chest where the player starts,
+/// every other one has stairs up there instead. The deepest level has the key in its furthest
+/// room, the others have stairs down.
+fn generate_level(depth: usize, data: &GameData, world: &mut World, rng: &mut GameRng) -> Level {
+    let (initial_map, rooms, _) = generate_map(MAP_WIDTH-SCREEN_WIDTH-1, MAP_HEIGHT-SCREEN_HEIGHT-1, rng);
+
+    let mut map = Map::new(MAP_WIDTH, MAP_HEIGHT, Tile::wall());
+
+    for y in 0..MAP_HEIGHT-SCREEN_HEIGHT-1 {
+        for x in 0..MAP_WIDTH-SCREEN_WIDTH-1 {
+            map.set(x+SCREEN_WIDTH/2, y+SCREEN_HEIGHT/2, initial_map.get(x, y));
+        }
+    }
+
+    let start = rooms[0].center();
+    let entrance = (start.0 + SCREEN_WIDTH / 2, start.1 + SCREEN_HEIGHT / 2);
+    let furthest_room = furthest_room(&rooms);
+
+    if depth == 0 {
+        let pos = Position { x: entrance.0, y: entrance.1 - 1, depth };
+        let glyph = Glyph { char: '&', color: colors::DARK_AMBER };
+        let chest = spawn_at(world, &mut map, pos, glyph, "chest", true).unwrap();
+        world.chests.insert(chest, Chest);
+    } else {
+        stairs(world, &mut map, Position { x: entrance.0, y: entrance.1, depth }, Stairs::Up);
+    }
+    let exit = if depth + 1 < DUNGEON_DEPTH {
+        let (x, y) = furthest_room.center();
+        let exit = (x + SCREEN_WIDTH / 2, y + SCREEN_HEIGHT / 2);
+        stairs(world, &mut map, Position { x: exit.0, y: exit.1, depth }, Stairs::Down);
+        Some(exit)
+    } else {
+        let mut i = 0;
+        loop {
+            let x = rng.gen_range(furthest_room.x1 + 1, furthest_room.x2);
+            let y = rng.gen_range(furthest_room.y1 + 1, furthest_room.y2);
+            let pos = Position { x: x + SCREEN_WIDTH / 2, y: y + SCREEN_HEIGHT / 2, depth };
+            match data.key().spawn(world, &mut map, pos) {
+                Some(_) => break,
+                None => {
+                    i += 1;
+                    if i > 40 {
+                        break;
+                    }
+                },
+            }
+        }
+        None
+    };
+
+    generate_objects(&rooms, depth, &mut map, world, data, rng);
+    generate_monsters(&rooms[1..], depth, &mut map, world, data, rng);
+    if exit.is_none() {
+        generate_boss(furthest_room, depth, &mut map, world, data, rng);
+    }
+
+    Level { map, entrance, exit, noises: Vec::new() }
+}
+
+fn gold_pile(world: &mut World, map: &mut Map, pos: Position, amount: u32) -> Option<EntityId> {
+    let glyph = Glyph { char: '$', color: colors::GOLD };
+    let pile = spawn_at(world, map, pos, glyph, "gold", false)?;
+    world.gold.insert(pile, amount);
+    Some(pile)
+}
+
+fn stairs(world: &mut World, map: &mut Map, pos: Position, direction: Stairs) -> EntityId {
+    let (char, name) = match direction {
+        Stairs::Up => ('<', "stairs up"),
+        Stairs::Down => ('>', "stairs down"),
+    };
+    let stairs = spawn_at(world, map, pos, Glyph { char, color: colors::WHITE }, name, false).unwrap();
+    world.stairs.insert(stairs, direction);
+    stairs
+}
+
+/// The room whose center is furthest from the first room's.
+fn furthest_room(rooms: &[Rect]) -> Rect {
+    let start = rooms[0].center();
+    let mut furthest_room: Rect = rooms[0];
+    let mut furthest_dist = 0;
+    for room in rooms {
+        let center = room.center();
+        let dist = (center.0 - start.0).pow(2) + (center.1 - start.1).pow(2);
+        if dist > furthest_dist {
+            furthest_room = *room;
+            furthest_dist = dist;
+        }
+    }
+    furthest_room
+}
+
+fn generate_objects(rooms: &[Rect], depth: usize, map: &mut Map, world: &mut World, data: &GameData, rng: &mut GameRng) {
+    for room in rooms {
+        let num_items = rng.gen_range(0, MAX_ROOM_ITEMS + 1);
+
+        for _ in 0..num_items {
+            let template = match pick_weighted(rng, &data.items, |i| i.spawn_weight) {
+                Some(template) => template,
+                None => break,
+            };
+            // only place it if the tile is not blocked
+            let mut i = 0;
+            loop {
+                let x = rng.gen_range(room.x1 + 1, room.x2);
+                let y = rng.gen_range(room.y1 + 1, room.y2);
+                let pos = Position { x: x + SCREEN_WIDTH / 2, y: y + SCREEN_HEIGHT / 2, depth };
+                match template.spawn(world, map, pos) {
+                    Some(_) => break,
+                    None => {
+                        i += 1;
+                        if i > 40 {
+                            break;
+                        }
+                    },
+                }
+            }
+        }
+    }
+}
+
+fn generate_monsters(rooms: &[Rect], depth: usize, map: &mut Map, world: &mut World, data: &GameData, rng: &mut GameRng) {
+    for room in rooms {
+        let mut num_monsters = rng.gen_range(0, MAX_ROOM_MONSTERS + 1);
+        if num_monsters == 0 && rng.gen() {
+            num_monsters = 1;
+        }
+        for _ in 0..num_monsters {
+            let template = match pick_weighted(rng, &data.monsters, |m| if m.boss { 0 } else { m.spawn_weight }) {
+                Some(template) => template,
+                None => return,
+            };
+            loop {
+                let x = rng.gen_range(room.x1 + 1, room.x2);
+                let y = rng.gen_range(room.y1 + 1, room.y2);
+                if template.spawn(world, map, Position { x: x + SCREEN_WIDTH/2, y: y + SCREEN_HEIGHT/2, depth }).is_some() {
+                    break;
+                }
+            }
+
+        }
+    }
+}
+
+/// Puts one of the bosses, if there are any, in `room`.
+fn generate_boss(room: Rect, depth: usize, map: &mut Map, world: &mut World, data: &GameData, rng: &mut GameRng) -> Option<EntityId> {
+    let bosses: Vec<_> = data.monsters.iter().filter(|m| m.boss).collect();
+    if bosses.is_empty() {
+        return None;
+    }
+    let template = bosses[rng.gen_range(0, bosses.len())];
+    for _ in 0..40 {
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
+        if let Some(boss) = template.spawn(world, map, Position { x: x + SCREEN_WIDTH / 2, y: y + SCREEN_HEIGHT / 2, depth }) {
+            return Some(boss);
+        }
+    }
+    None
+}
+
+fn handle_camera(state: &mut GameState) {
+    let (x, y) = state.player_pos();
+    if x - state.camera_pos.0 < -1 {
+        state.camera_pos.0 -= 1
+    } else if x - state.camera_pos.0 > 0 {
+        state.camera_pos.0 += 1
+    }
+    if y - state.camera_pos.1 < -1 {
+        state.camera_pos.1 -= 1
+    } else if y - state.camera_pos.1 > 0 {
+        state.camera_pos.1 += 1
+    }
+}
+
+fn compute_fov(state: &mut GameState, force: bool) -> bool {
+    let (px, py) = state.player_pos();
+    if force || state.prev_player_pos != (px, py) {
+        state.fov_map.compute_fov(px, py, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+        let level = &mut state.levels[state.depth];
+        for y in 0..(level.map.height - 1) {
+            for x in 0..(level.map.width - 1) {
+                let visible = state.fov_map.is_in_fov(x, y);
+                let explored = &mut level.map.get_mut(x, y).explored;
+                if visible {
+                    *explored = true;
+                }
+            }
+        }
+        if let Some(id) = state.recent_enemy {
+            let visible = state.world.fighters.has(id) && state.world.position(id).is_some_and(|pos| {
+                pos.depth == state.depth && state.fov_map.is_in_fov(pos.x, pos.y)
+            });
+            if !visible {
+                state.recent_enemy = None;
+            }
+        }
+        true
+    } else {
+        false
+    }
+}
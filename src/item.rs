@@ -0,0 +1,290 @@
+use data::GameData;
+use entity::{take_damage, Bonus};
+use game::{GameEvent, Stat};
+use world::{EntityId, World};
+
+/// How many different things the player can carry. A stack only takes up one slot.
+pub const INVENTORY_CAPACITY: usize = 26;
+
+/// What an item does when it's used. How strong it is comes from the item's `magnitude`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Effect {
+    Heal,
+    RaiseMaxHp,
+    RaisePower,
+    RaiseDefense,
+    /// Opens the chest. Carried rather than used.
+    Unlock,
+    /// Worn in its `slot` for the stats it gives.
+    Equip,
+    /// Strikes the closest monster in sight.
+    Lightning,
+    /// Burns everything around the tile it's aimed at, the player included.
+    Fireball,
+    /// Makes the monster it's aimed at stumble around for `magnitude` turns.
+    Confusion,
+}
+
+impl Effect {
+    /// Whether an item with this effect is used up by using it.
+    pub fn is_consumable(self) -> bool {
+        self != Effect::Unlock && self != Effect::Equip
+    }
+
+    /// Whether it's drunk, as opposed to read or kept.
+    pub fn is_potion(self) -> bool {
+        self.is_consumable() && !self.is_spell()
+    }
+
+    /// Whether it's a scroll that casts a spell on something other than the player.
+    pub fn is_spell(self) -> bool {
+        self == Effect::Lightning || self == Effect::Fireball || self == Effect::Confusion
+    }
+
+    /// Whether the player has to pick a tile to aim it at.
+    pub fn needs_target(self) -> bool {
+        self == Effect::Fireball || self == Effect::Confusion
+    }
+
+    /// Applies the effect to `target`. Returns `false` if it had no effect, in which case the
+    /// item shouldn't be used up. Spells are cast by the game instead, and have no effect here.
+    pub fn apply(self, magnitude: i32, world: &mut World, target: EntityId, events: &mut Vec<GameEvent>) -> bool {
+        use self::Effect::*;
+        match self {
+            Heal => {
+                take_damage(world, target, -magnitude, events);
+                true
+            },
+            RaiseMaxHp => match world.fighters.get_mut(target) {
+                Some(f) => {
+                    f.max_hp += magnitude;
+                    f.hp += magnitude;
+                    events.push(GameEvent::StatRaised(Stat::MaxHp));
+                    true
+                },
+                None => false,
+            },
+            RaisePower => match world.fighters.get_mut(target) {
+                Some(f) => {
+                    f.power += magnitude;
+                    events.push(GameEvent::StatRaised(Stat::Power));
+                    true
+                },
+                None => false,
+            },
+            RaiseDefense => match world.fighters.get_mut(target) {
+                Some(f) => {
+                    f.defense += magnitude;
+                    events.push(GameEvent::StatRaised(Stat::Defense));
+                    true
+                },
+                None => false,
+            },
+            Unlock | Equip | Lightning | Fireball | Confusion => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Item {
+    pub effect: Effect,
+    pub magnitude: i32,
+    /// Stackable items share one inventory slot with others of the same name.
+    pub stackable: bool,
+    pub count: u32,
+    pub equipment: Option<Equipment>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Slot {
+    Weapon,
+    Armor,
+    Shield,
+    Ring,
+}
+
+impl Slot {
+    pub fn name(self) -> &'static str {
+        match self {
+            Slot::Weapon => "weapon",
+            Slot::Armor => "armor",
+            Slot::Shield => "shield",
+            Slot::Ring => "ring",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Equipment {
+    pub slot: Slot,
+    pub bonus: Bonus,
+    pub equipped: bool,
+}
+
+/// What the player carries. The items are still in the `World`, just without a position.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Inventory {
+    pub items: Vec<EntityId>,
+}
+
+impl Inventory {
+    /// Adds a picked up item, merging it into an existing stack if it can. If there's no room
+    /// for it the item is handed back.
+    pub fn add(&mut self, world: &mut World, id: EntityId) -> Result<(), EntityId> {
+        if let Some(&item) = world.items.get(id) {
+            if item.stackable {
+                let stack = self.items.iter().cloned().find(|&other| {
+                    world.name(other) == world.name(id) && world.items.get(other).is_some_and(|i| i.stackable)
+                });
+                if let Some(stack) = stack {
+                    if let Some(other) = world.items.get_mut(stack) {
+                        other.count += item.count;
+                    }
+                    world.despawn(id);
+                    return Ok(());
+                }
+            }
+        }
+        if self.items.len() >= INVENTORY_CAPACITY {
+            return Err(id);
+        }
+        world.remove_position(id);
+        self.items.push(id);
+        Ok(())
+    }
+
+    /// Takes the whole slot at `index` out of the inventory.
+    pub fn remove(&mut self, world: &mut World, index: usize) -> EntityId {
+        let id = self.items.remove(index);
+        unequip(world, id);
+        id
+    }
+
+    /// Takes a single item off the stack at `index`, removing the slot if it was the last one.
+    pub fn split_one(&mut self, world: &mut World, index: usize) -> EntityId {
+        let one = world.duplicate(self.items[index]);
+        if let Some(item) = world.items.get_mut(one) {
+            item.count = 1;
+        }
+        unequip(world, one);
+        self.take_one(world, index);
+        one
+    }
+
+    /// Takes one item out of the slot at `index`, removing the slot if it was the last one.
+    pub fn take_one(&mut self, world: &mut World, index: usize) {
+        let id = self.items[index];
+        let remaining = match world.items.get_mut(id) {
+            Some(item) => {
+                item.count = item.count.saturating_sub(1);
+                item.count
+            },
+            None => 0,
+        };
+        if remaining == 0 {
+            self.items.remove(index);
+            world.despawn(id);
+        }
+    }
+
+    pub fn has(&self, world: &World, effect: Effect) -> bool {
+        self.items.iter().any(|&id| world.items.get(id).is_some_and(|i| i.effect == effect))
+    }
+
+    /// The combined bonus of everything that's equipped.
+    pub fn bonus(&self, world: &World) -> Bonus {
+        self.items.iter()
+            .filter_map(|&id| world.items.get(id).and_then(|i| i.equipment))
+            .filter(|e| e.equipped)
+            .fold(Bonus::default(), |total, e| Bonus {
+                max_hp: total.max_hp + e.bonus.max_hp,
+                defense: total.defense + e.bonus.defense,
+                power: total.power + e.bonus.power,
+            })
+    }
+
+    /// Slots holding things that can be equipped.
+    pub fn equipment(&self, world: &World) -> Vec<usize> {
+        (0..self.items.len())
+            .filter(|&i| world.items.get(self.items[i]).is_some_and(|item| item.equipment.is_some()))
+            .collect()
+    }
+
+    /// The first slot holding something that can be drunk.
+    pub fn first_potion(&self, world: &World) -> Option<usize> {
+        self.items.iter().position(|&id| world.items.get(id).is_some_and(|i| i.effect.is_potion()))
+    }
+}
+
+fn unequip(world: &mut World, id: EntityId) {
+    if let Some(equipment) = world.items.get_mut(id).and_then(|i| i.equipment.as_mut()) {
+        equipment.equipped = false;
+    }
+}
+
+/// How an inventory slot is listed, e.g. "healing potion (x3)" or "dagger (on)".
+pub fn label(world: &World, id: EntityId) -> String {
+    let name = world.name(id);
+    let item = match world.items.get(id) {
+        Some(&item) => item,
+        None => return name.to_string(),
+    };
+    if item.equipment.is_some_and(|e| e.equipped) {
+        format!("{} (on)", name)
+    } else if item.count > 1 {
+        format!("{} (x{})", name, item.count)
+    } else {
+        name.to_string()
+    }
+}
+
+/// A few lines about what an item does, for inspecting it. Uses the `description` from its
+/// template in `data` if it has one.
+pub fn describe(data: &GameData, world: &World, id: EntityId) -> String {
+    let item = match world.items.get(id) {
+        Some(&item) => item,
+        None => return format!("It's {}.", with_article(world.name(id))),
+    };
+    if let Some(description) = data.item(world.name(id)).and_then(|t| t.description.as_ref()) {
+        return format!("{}. {}", label(world, id), description);
+    }
+    let what = match item.effect {
+        Effect::Heal => format!("Drinking it restores {} hp.", item.magnitude),
+        Effect::RaiseMaxHp => format!("Drinking it raises your max hp by {}.", item.magnitude),
+        Effect::RaisePower => format!("Drinking it raises your power by {}.", item.magnitude),
+        Effect::RaiseDefense => format!("Drinking it raises your defense by {}.", item.magnitude),
+        Effect::Unlock => "It looks like it would fit the chest.".to_string(),
+        Effect::Lightning => format!("Reading it strikes the closest enemy for {} hp.", item.magnitude),
+        Effect::Fireball => format!("Reading it burns everything around where you aim for {} hp.", item.magnitude),
+        Effect::Confusion => format!("Reading it confuses the enemy you aim at for {} turns.", item.magnitude),
+        Effect::Equip => match item.equipment {
+            Some(e) => {
+                let mut bonuses = vec![];
+                if e.bonus.power != 0 {
+                    bonuses.push(format!("{:+} power", e.bonus.power));
+                }
+                if e.bonus.defense != 0 {
+                    bonuses.push(format!("{:+} defense", e.bonus.defense));
+                }
+                if e.bonus.max_hp != 0 {
+                    bonuses.push(format!("{:+} max hp", e.bonus.max_hp));
+                }
+                let worn = if e.equipped { "You're wearing it" } else { "Worn" };
+                format!("{} as your {}: {}.", worn, e.slot.name(), bonuses.join(", "))
+            },
+            None => String::new(),
+        },
+    };
+    format!("{}. {}", label(world, id), what)
+}
+
+/// "a goblin", "an apple".
+pub fn with_article(name: &str) -> String {
+    let article = match name.chars().next() {
+        Some(c) if "aeiouAEIOU".contains(c) => "an",
+        _ => "a",
+    };
+    format!("{} {}", article, name)
+}
@@ -0,0 +1,40 @@
+extern crate tcod;
+extern crate bresenham;
+extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
+#[cfg(target_os = "linux")]
+extern crate gpio_cdev;
+
+pub mod events;
+pub mod messages;
+pub mod stats;
+pub mod data;
+pub mod map;
+pub mod path;
+pub mod world;
+pub mod entity;
+pub mod ai;
+pub mod item;
+pub mod game;
+pub mod save;
+pub mod replay;
+pub mod bindings;
+pub mod options;
+pub mod win;
+pub mod reveal;
+
+// actual size of the window (in characters)
+pub const SCREEN_WIDTH: i32 = 32;
+pub const SCREEN_HEIGHT: i32 = 24;
+pub const PANEL_HEIGHT: i32 = 7;
+pub const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
+
+// size of map (in characters)
+pub const MAP_WIDTH: i32 = 80 + SCREEN_WIDTH + 1;
+pub const MAP_HEIGHT: i32 = 45 + SCREEN_HEIGHT + 1;
+
+pub const TORCH_RADIUS: i32 = 7;
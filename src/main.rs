@@ -1,29 +1,42 @@
 extern crate tcod;
-extern crate bresenham;
 extern crate rand;
+extern crate giftrogue;
 
-use std::process::Command;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
 
 use tcod::console::*;
 use tcod::colors;
-use tcod::map::{Map as FovMap, FovAlgorithm};
+use tcod::map::Map as FovMap;
+use tcod::input::{Key, KeyCode};
 use colors::Color;
-use bresenham::Bresenham;
-use rand::Rng;
 
-// actual size of the window (in characters)
-const SCREEN_WIDTH: i32 = 32;
-const SCREEN_HEIGHT: i32 = 24;
+use giftrogue::{SCREEN_WIDTH, SCREEN_HEIGHT, PANEL_HEIGHT, MSG_HEIGHT, MAP_WIDTH, MAP_HEIGHT, TORCH_RADIUS};
+use giftrogue::bindings::{key_name, Command, KeyBindings, BINDINGS_FILE};
+use giftrogue::data::{GameData, Profile, DATA_DIR};
+use giftrogue::events::publish;
+use giftrogue::game::{Action, GameEvent, GameStage, GameState, Stat};
+use giftrogue::item::{self, INVENTORY_CAPACITY};
+use giftrogue::map::Map;
+use giftrogue::messages::{print_message, Category, Message, Messages, FILTERS};
+use giftrogue::options::{GameOptions, OPTIONS_FILE};
+use giftrogue::replay::{Replay, REPLAY_FILE};
+use giftrogue::save::{delete_save, load_game, save_exists, save_game, SAVE_FILE};
+use giftrogue::stats::Statistics;
+use giftrogue::world::EntityId;
+use giftrogue::reveal::{Pulse, Reveal, REVEAL_FILE};
+use giftrogue::win::{RunningCommand, WinAction, WIN_ACTION_FILE};
+
 const BAR_WIDTH: i32 = 16;
-const PANEL_HEIGHT: i32 = 7;
+// room right of the player's HP bar for the depth, and of their XP bar for their level
+const DEPTH_WIDTH: i32 = 4;
 const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
 const MSG_X: i32 = 1;
+// the player's bars and the enemy's take up the rows above the messages
+const MSG_Y: i32 = 2;
 const MSG_WIDTH: i32 = SCREEN_WIDTH - MSG_X - 1;
-const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
-
-// size of map (in characters)
-const MAP_WIDTH: i32 = 80 + SCREEN_WIDTH + 1;
-const MAP_HEIGHT: i32 = 45 + SCREEN_HEIGHT + 1; 
+const WINDOW_WIDTH: i32 = SCREEN_WIDTH - 4;
 
 const LIMIT_FPS: i32 = 20;  // 20 frames-per-second maximum
 
@@ -36,793 +49,774 @@ const COLOR_GROUND_LIGHT: Color = Color { r: 110, g: 10, b: 20 };
 // const COLOR_GROUND_DARK: Color = colors:: DARKEST_GREY;
 // const COLOR_GROUND_LIGHT: Color = colors:: DARK_GREY;
 
-const FOV_ALGO: FovAlgorithm = FovAlgorithm::Shadow;
-const FOV_LIGHT_WALLS: bool = true;
-const TORCH_RADIUS: i32 = 7;
-
-const ROOM_MAX_SIZE: i32 = 12;
-const ROOM_MIN_SIZE: i32 = 6;
-const MAX_ROOMS: i32 = 30;
-
-const MAX_ROOM_MONSTERS: i32 = 3;
-const MAX_ROOM_ITEMS: i32 = 1;
-
-type Messages = Vec<(String, Color)>;
-
-fn print_message<T: Into<String>>(messages: &mut Messages, message: T, color: Color) {
-    if messages.len() == MSG_HEIGHT {
-        messages.remove(0);
-    }
-
-    messages.push((message.into(), color));
-}
+const REPLAY_FRAMES_PER_INPUT: u32 = 4;
+const FAST_FORWARD_INPUTS_PER_FRAME: usize = 10;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum PlayerAction {
-    TookTurn,
+    Act(Action),
+    ChooseEquipment,
+    OpenInventory,
+    Look,
+    ShowHistory,
     DidntTakeTurn,
     Exit,
 }
 
-#[derive(Clone,Copy, Debug, PartialEq)]
-enum DeathCallback {
-    Player,
-    Monster,
+struct Tcod {
+    root: Root,
+    con: Offscreen,
+    panel: Offscreen,
 }
 
-impl DeathCallback {
-    fn callback(self, entity: &mut Entity, messages: &mut Messages) {
-        use DeathCallback::*;
-        let cb: fn(&mut Entity, &mut Messages) = match self {
-            Player => player_death,
-            Monster => monster_death,
-        };
-        cb(entity, messages);
-    }
-}
-
-fn player_death(player: &mut Entity, messages: &mut Messages) {
-    print_message(messages, "You died!", colors::RED);
-    print_message(messages, "Press Start to start a new game!", colors::CYAN);
-    player.char = '%';
-    player.color = colors::DARK_RED;
-    player.alive = false;
+/// What happens outside the game when it's won. Replays use the default, which does nothing.
+#[derive(Default)]
+struct Gift {
+    win_action: WinAction,
+    reveal: Reveal,
+    /// The win action's command, for as long as it's running.
+    command: Option<RunningCommand>,
+    /// The reveal's pulse, for as long as it's holding the lines on.
+    pulse: Option<Pulse>,
+    /// Whether the gift has been opened, which is where the game ends.
+    opened: bool,
 }
 
-fn monster_death(monster: &mut Entity, messages: &mut Messages) {
-    print_message(messages, format!("{} died!", monster.name), colors::RED);
-    if monster.char == 'T' {
-        monster.char = '%'
-    } else {
-        monster.char = '.';
-    }
-    monster.blocks = false;
-    monster.ai = None;
-    monster.fighter = None;
-    monster.alive = false;
+/// What the front-end has to do after an action went through `GameState::step`.
+#[derive(Default)]
+struct StepOutcome {
+    rerender_map: bool,
+    run_over: bool,
+    exit: bool,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-struct Fighter {
-    max_hp: i32,
-    hp: i32,
-    defense: i32,
-    power: i32,
-    on_death: DeathCallback,
-}
+fn main() {
+    let root = Root::initializer()
+        .font("dejavu10x10_gs_tc.png", FontLayout::Tcod)
+        .font_type(FontType::Greyscale)
+        .size(SCREEN_WIDTH, SCREEN_HEIGHT)
+        .title("Rust/libtcod tutorial")
+        .init();
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-struct Chest;
+    let mut tcod = Tcod {
+        root,
+        con: Offscreen::new(MAP_WIDTH, MAP_HEIGHT),
+        panel: Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT),
+    };
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-enum Item {
-    Heal,
-    Key
-}
+    tcod::system::set_fps(LIMIT_FPS);
+    tcod::input::show_cursor(false);
 
-struct Inventory {
-    healing_potions: i32,
-    has_key: bool
-}
+    let bindings = load_or_default(BINDINGS_FILE, KeyBindings::load, "using the default keys");
+    let data = Rc::new(load_or_default(DATA_DIR, GameData::load, "using the bundled data"));
+    let mut gift = Gift {
+        win_action: load_or_default(WIN_ACTION_FILE, WinAction::load, "nothing will happen when the chest opens"),
+        reveal: load_or_default(REVEAL_FILE, Reveal::load, "the gift won't be revealed"),
+        ..Gift::default()
+    };
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-struct Ai;
-
-impl Ai {
-    pub fn take_turn(monster_id: usize, state: &mut GameState, messages: &mut Messages) {
-        let monster = &mut state.npcs[monster_id];
-        if state.fov_map.is_in_fov(monster.x, monster.y) {
-            if !monster.next_to(&state.player) {
-                monster.move_towards(state.player.x, state.player.y, &mut state.map);
-            } else if state.player.fighter.map_or(false, |f| f.hp > 0) {
-                monster.attack(&mut state.player, messages);
-            }
+    if let Some(path) = arg_value("--replay") {
+        match Replay::load(&path) {
+            Ok(replay) => play_back(&mut tcod, &bindings, &replay, &data),
+            Err(e) => println!("couldn't load {}: {}", path, e),
         }
+        return;
     }
-}
-
-#[derive(Debug)]
-struct Entity {
-    blocks: bool,
-    x: i32,
-    y: i32,
-    char: char,
-    color: Color,
-    name: String,
-    alive: bool,
-    fighter: Option<Fighter>,
-    ai: Option<Ai>,
-    item: Option<Item>,
-    chest: Option<Chest>,
-}
 
-impl Entity {
-    pub fn new<S: Into<String>>(x: i32, y: i32, char: char, color: Color, name: S, map: &mut Map, blocks: bool, alive: bool) -> Option<Self> {
-        let blocked = map.get(x, y).blocks_movement;
-        if blocked {
-            None
-        } else {
-            if blocks {
-                map.set(x, y, Tile::entity());
-            }
-            Some(Entity {
-                blocks,
-                x,
-                y,
-                char,
-                name: name.into(),
-                color,
-                alive,
-                fighter: None,
-                ai: None,
-                item: None,
-                chest: None,
-            })
-        }
-    }
-
-    pub fn move_towards(&mut self, target_x: i32, target_y: i32, map: &mut Map) {
-        // vector from this object to the target, and distance
-        let dx = target_x - self.x;
-        let dy = target_y - self.y;
-        if dx == 0 && dy == 0 {
-            return
-        }
-        let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
-
-
-        // normalize it to length 1 (preserving direction), then round it and
-        // convert to integer so the movement is restricted to the map grid
-        let mut dx = (dx as f32 / distance).round() as i32;
-        let mut dy = (dy as f32 / distance).round() as i32;
-        if dx.abs() > dy.abs() {
-            dy = 0;
-        } else {
-            dx = 0;
-        }
-        self.move_by(dx, dy, map);
-    }
+    let options = load_or_default(OPTIONS_FILE, GameOptions::load, "using the default options");
+    let (mut state, mut messages, mut recording) = match load_or_new_game(&mut tcod.root, &bindings, &data, options) {
+        Some(game) => game,
+        None => return,
+    };
 
-    // pub fn distance_to(&self, other: &Entity) -> f32 {
-    //     let dx = other.x - self.x;
-    //     let dy = other.y - self.y;
-    //     ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
-    // }
+    let mut stats = Statistics::default();
 
-    pub fn next_to(&self, other: &Entity) -> bool {
-        let dx = (other.x - self.x).abs();
-        let dy = (other.y - self.y).abs();
-        (dx == 0 && dy == 1) || (dy == 0 && dx == 1) || (dx == 0 && dy == 0)
-    }
+    // Render initial state
+    render_all(&mut tcod, &state, true, &messages);
 
-    pub fn move_by(&mut self, dx: i32, dy: i32, map: &mut Map) {
-        if !map.get(self.x + dx, self.y + dy).blocks_movement {
-            if self.blocks {
-                map.set(self.x, self.y, Tile::empty());
-            }
-            self.x += dx;
-            self.y += dy;
-            if self.blocks {
-                map.set(self.x, self.y, Tile::entity());
-            }
+    // Loop
+    while !tcod.root.window_closed() {
+        // handle keys and exit game if needed
+        let key = next_key(&mut tcod, &state, &mut messages, &mut gift);
+        if gift.opened {
+            break;
         }
-    }
-
-    pub fn move_or_attack(&mut self, dx: i32, dy: i32, map: &mut Map, enemies: &mut [Entity], objects: &mut [Entity], has_key: bool, stage: &mut GameStage, messages: &mut Messages) -> Option<usize> {
-        if !map.get(self.x + dx, self.y + dy).blocks_movement {
-            if self.blocks {
-                map.set(self.x, self.y, Tile::empty());
-            }
-            self.x += dx;
-            self.y += dy;
-            if self.blocks {
-                map.set(self.x, self.y, Tile::entity());
-            }
-        } else {
-            let x = self.x + dx;
-            let y = self.y + dy;
-            let enemy_id = enemies.iter().position(|enemy| {
-                enemy.x == x && enemy.y == y
-            });
-
-            match enemy_id {
-                Some(id) => {
-                    let enemy = &mut enemies[id];
-                    self.attack(enemy, messages);
-                    if !enemy.blocks {
-                        map.set(enemy.x, enemy.y, Tile::empty());
-                    }
-                    if enemy.alive {
-                        return Some(id);
-                    } else {
-                        return None;
-                    }
+        let action = match handle_keys(key, &bindings) {
+            PlayerAction::Exit => break,
+            PlayerAction::Act(action) => action,
+            PlayerAction::ChooseEquipment => match choose_equipment(&mut tcod.root, &bindings, &state, &mut messages) {
+                Some(index) => Action::Equip(index),
+                None => {
+                    render_all(&mut tcod, &state, true, &messages);
+                    continue;
                 },
+            },
+            PlayerAction::OpenInventory => match open_inventory(&mut tcod.root, &bindings, &state, &mut messages) {
+                Some(action) => action,
                 None => {
-                    let obj_id = objects.iter().position(|obj| {
-                        obj.x == x && obj.y == y
-                    });
-
-                    match obj_id {
-                        Some(id) => {
-                            if objects[id].chest.is_some() {
-                                if has_key {
-                                    open_chest(messages, stage);
-                                } else {
-                                    print_message(messages, "You need a key to open this chest, not a sword.", colors::WHITE);
-                                }
-                            }
-                        },
-                        None => {
-                            print_message(messages, "You try to attack... the wall?", colors::GREY);
-                        }
-                    }
-                    return None;
+                    render_all(&mut tcod, &state, true, &messages);
+                    continue;
+                },
+            },
+            PlayerAction::Look => {
+                if state.stage == GameStage::Playing {
+                    look(&mut tcod.root, &bindings, &state);
                 }
-            }
+                render_all(&mut tcod, &state, true, &messages);
+                continue;
+            },
+            PlayerAction::ShowHistory => {
+                show_history(&mut tcod.root, &bindings, &messages, &state.data.profile);
+                render_all(&mut tcod, &state, true, &messages);
+                continue;
+            },
+            PlayerAction::DidntTakeTurn => continue,
+        };
+        if let Some(ref mut replay) = recording {
+            replay.record(key_name(key), action);
         }
-        None
-    }
 
-    pub fn take_damage(&mut self, damage: i32, messages: &mut Messages) {
-        if let Some(f) = self.fighter.as_mut() {
-            f.hp -= damage;
-            f.hp = std::cmp::min(f.hp, f.max_hp);
-        }
-        if let Some(f) = self.fighter {
-            if f.hp <= 0 {
-                f.on_death.callback(self, messages);
+        let outcome = play_action(&mut tcod, &bindings, &mut state, &mut messages, &mut stats, action, &mut gift);
+        if outcome.run_over {
+            // permadeath: a finished run can't be continued
+            if let Err(e) = delete_save(SAVE_FILE) {
+                println!("couldn't delete {}: {}", SAVE_FILE, e);
             }
         }
-    }
+        if outcome.exit {
+            break;
+        }
+        render_all(&mut tcod, &state, outcome.rerender_map, &messages);
 
-    pub fn attack(&mut self, target: &mut Entity, messages: &mut Messages) {
-        let damage = self.fighter.map_or(0, |f| f.power) - target.fighter.map_or(0, |f| f.defense);
-        if damage > 0 {
-            let color = if self.name == "James" {
-                colors::WHITE
-            } else {
-                colors::RED
+        while state.can_level_up() {
+            let action = match choose_stat(&mut tcod.root, &bindings, &state) {
+                Some(stat) => Action::LevelUp(stat),
+                None => break,
             };
-            print_message(messages, format!("{} attacks {} for {} hp!", self.name, target.name, damage), color);
-            target.take_damage(damage, messages);
-        } else {
-            print_message(messages, format!("{} attacks {} but it has no effect... ", self.name, target.name), colors::GREY);
+            if let Some(ref mut replay) = recording {
+                replay.record("level up", action);
+            }
+            play_action(&mut tcod, &bindings, &mut state, &mut messages, &mut stats, action, &mut gift);
+            render_all(&mut tcod, &state, true, &messages);
         }
     }
 
-    pub fn draw(&self, con: &mut Console) {
-        con.set_default_foreground(self.color);
-        con.put_char(self.x, self.y, self.char, BackgroundFlag::None);
+    // closing the window halfway through the pulse mustn't leave the lines on
+    if let Some(pulse) = gift.pulse.take() {
+        if let Err(e) = pulse.wait() {
+            println!("couldn't reveal the gift: {}", e);
+        }
     }
 
-    pub fn clear(&self, con: &mut Console) {
-        con.put_char(self.x, self.y, ' ', BackgroundFlag::None);
+    if let Some(mut replay) = recording {
+        replay.finish(&state);
+        if let Err(e) = replay.save(REPLAY_FILE) {
+            println!("couldn't save {}: {}", REPLAY_FILE, e);
+        }
+    }
+    if state.stage == GameStage::Playing {
+        if let Err(e) = save_game(SAVE_FILE, &mut state, &messages) {
+            println!("couldn't save to {}: {}", SAVE_FILE, e);
+        }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-struct Tile {
-    blocks_movement: bool,
-    blocks_sight: bool,
-    explored: bool,
+/// Runs one action through the game and applies the resulting events to the front-end.
+/// Both live play and replays go through here, so they can't drift apart.
+fn play_action(tcod: &mut Tcod, bindings: &KeyBindings, state: &mut GameState, messages: &mut Messages, stats: &mut Statistics,
+               action: Action, gift: &mut Gift) -> StepOutcome {
+    let mut outcome = StepOutcome::default();
+    let events = state.step(action);
+    messages.turn = state.turn;
+    publish(&events, state, &mut [messages, stats]);
+    for event in events {
+        match event {
+            GameEvent::FovRecomputed => outcome.rerender_map = true,
+            GameEvent::NewGame => {
+                println!("seed {}", state.seed);
+                tcod.root.clear();
+                tcod.con.clear();
+                tcod.panel.clear();
+                outcome.rerender_map = true;
+            },
+            GameEvent::LevelChanged(_) => {
+                tcod.con.clear();
+                outcome.rerender_map = true;
+            },
+            GameEvent::RevealGift => match gift.reveal.reveal() {
+                Ok(Some(pulse)) => gift.pulse = Some(pulse),
+                Ok(None) => outcome.exit = true,
+                Err(e) => reveal_failed(messages, &e),
+            },
+            GameEvent::StageChanged(GameStage::Won) => {
+                gift.command = gift.win_action.run(messages);
+                print_message(messages, stats.summary(), Category::System);
+                outcome.run_over = true;
+            },
+            GameEvent::StageChanged(GameStage::GameOver) => {
+                print_message(messages, stats.summary(), Category::System);
+                print_message(messages, start_prompt(bindings), Category::System);
+                outcome.run_over = true;
+            },
+            _ => {},
+        }
+    }
+    outcome
 }
 
-impl Tile {
-    pub fn empty() -> Self {
-        Tile { blocks_movement: false, blocks_sight: false, explored: false }
+/// Tells the player how to start over, with the keys Start is actually bound to.
+fn start_prompt(bindings: &KeyBindings) -> String {
+    let keys = bindings.keys(Command::Start);
+    if keys.is_empty() {
+        format!("Bind a key to `start` in {} to play again.", BINDINGS_FILE)
+    } else {
+        format!("Press {} to start a new game!", keys.join(" or "))
     }
+}
 
-    pub fn wall() -> Self {
-        Tile { blocks_movement: true, blocks_sight: true, explored: false }
-    }
+fn reveal_failed(messages: &mut Messages, e: &io::Error) {
+    println!("couldn't reveal the gift: {}", e);
+    print_message(messages, format!("The gift won't open: {}", e), Category::Danger);
+}
 
-    pub fn entity() -> Self {
-        Tile { blocks_movement: true, blocks_sight: false, explored: false }
-    }
+/// Waits for a key press. While the win action's command or the reveal's pulse is running,
+/// they're checked on every frame instead, so the player hears about them failing straight
+/// away. Once the pulse is over and the gift open, a key that does nothing is handed back.
+fn next_key(tcod: &mut Tcod, state: &GameState, messages: &mut Messages, gift: &mut Gift) -> Key {
+    use tcod::input::{self, Event};
 
-    pub fn is_wall(&self) -> bool {
-        self.blocks_movement && self.blocks_sight
+    while gift.command.is_some() || gift.pulse.is_some() {
+        if gift.command.as_ref().is_some_and(|command| command.finished(messages)) {
+            gift.command = None;
+        }
+        if let Some(result) = gift.pulse.as_ref().and_then(Pulse::finished) {
+            gift.pulse = None;
+            match result {
+                Ok(()) => {
+                    gift.opened = true;
+                    return Key::default();
+                },
+                Err(e) => reveal_failed(messages, &e),
+            }
+        }
+        render_all(tcod, state, false, messages);
+        if let Some((_, Event::Key(key))) = input::check_for_event(input::KEY_PRESS) {
+            return key;
+        }
+        if tcod.root.window_closed() {
+            return Key::default();
+        }
     }
+    tcod.root.wait_for_keypress(true)
 }
 
-struct Map {
-    width: i32,
-    height: i32,
-    data: Vec<Vec<Tile>>,
-}
+/// Plays a recorded run back through `play_action`, then checks the final state against the
+/// recording. Space pauses, Right steps one input while paused, Tab toggles fast-forward and
+/// Escape quits.
+fn play_back(tcod: &mut Tcod, bindings: &KeyBindings, replay: &Replay, data: &Rc<GameData>) {
+    use tcod::input::{self, Event};
+
+    let (mut state, mut messages) = new_game(replay.seed, replay.options, data);
+    let mut gift = Gift::default();
+    let mut stats = Statistics::default();
+    let mut next = 0;
+    let mut paused = false;
+    let mut fast_forward = false;
+    let mut checked = false;
+    let mut rerender_map = true;
+    let mut frame: u32 = 0;
+
+    while !tcod.root.window_closed() {
+        let mut step = false;
+        if let Some((_, Event::Key(key))) = input::check_for_event(input::KEY_PRESS) {
+            match key.code {
+                KeyCode::Spacebar => paused = !paused,
+                KeyCode::Right if paused => step = true,
+                KeyCode::Tab => fast_forward = !fast_forward,
+                KeyCode::Escape => return,
+                _ => {},
+            }
+        }
 
-impl Map {
-    pub fn new(width: i32, height: i32, default_tile: Tile) -> Self {
-        Map {
-            width,
-            height,
-            data: vec![vec![default_tile; height as usize]; width as usize]
+        let batch = if step {
+            1
+        } else if paused {
+            0
+        } else if fast_forward {
+            FAST_FORWARD_INPUTS_PER_FRAME
+        } else if frame.is_multiple_of(REPLAY_FRAMES_PER_INPUT) {
+            1
+        } else {
+            0
+        };
+        for input in replay.inputs.iter().skip(next).take(batch) {
+            rerender_map |= play_action(tcod, bindings, &mut state, &mut messages, &mut stats, input.action, &mut gift).rerender_map;
+            next += 1;
         }
-    }
 
-    pub fn get(&self, x: i32, y: i32) -> Tile {
-        self.data[x as usize][y as usize]
-    }
+        if next == replay.inputs.len() && !checked {
+            checked = true;
+            let (msg, category) = match replay.matches(&state) {
+                Some(true) => ("Replay finished: the final state matches the recording.", Category::System),
+                Some(false) => ("Replay finished: the final state differs from the recording!", Category::Danger),
+                None => ("Replay finished: the recording has no final state to check.", Category::System),
+            };
+            println!("{}", msg);
+            print_message(&mut messages, msg, category);
+        }
 
-    pub fn get_mut(&mut self, x: i32, y: i32) -> &mut Tile {
-        &mut self.data[x as usize][y as usize]
+        render_all(tcod, &state, rerender_map, &messages);
+        rerender_map = false;
+        frame = frame.wrapping_add(1);
     }
+}
 
-    pub fn set(&mut self, x: i32, y: i32, tile: Tile) {
-        self.data[x as usize][y as usize] = tile
-    } 
+fn new_game(seed: u64, options: GameOptions, data: &Rc<GameData>) -> (GameState, Messages) {
+    println!("seed {}", seed);
+    let state = GameState::new(seed, options, data.clone());
 
-    pub fn draw(&self, con: &mut Console, player_pos: (i32, i32), fov_map: &FovMap) {
-        for y in 0..(self.height - 1) {
-            for x in 0..(self.width - 1) {
-                if self.get(x, y).explored {
-                    let visible = fov_map.is_in_fov(x, y);
-                    let is_wall = self.get(x, y).is_wall();
-                    let col = match (visible, is_wall) {
-                        (false, true) => COLOR_WALL_DARK,
-                        (false, false) => COLOR_GROUND_DARK,
-                        (true, true) => colors::lerp(COLOR_WALL_LIGHT, COLOR_WALL_DARK, ((((x - player_pos.0).pow(2) + (y - player_pos.1).pow(2)) as f32).sqrt() / TORCH_RADIUS as f32).powi(2)),
-                        (true, false) => colors::lerp(COLOR_GROUND_LIGHT, COLOR_GROUND_DARK, ((((x - player_pos.0).pow(2) + (y - player_pos.1).pow(2)) as f32).sqrt() / TORCH_RADIUS as f32).powi(2)),
-                    };
-                    if is_wall {
-                        con.put_char_ex(x, y, '#',
-                                        Color {
-                                            r: std::cmp::max(col.r as i16 - 8, 0) as u8,
-                                            g: std::cmp::max(col.g as i16 - 8, 0) as u8,
-                                            b: std::cmp::max(col.b as i16 - 8, 0) as u8,
-                                        }, 
-                                        col);
-                    } else {
-                        con.set_char_background(x, y, col, BackgroundFlag::Set);
-                    }
-                }
-            }
+    let mut messages = Messages::default();
+
+    let profile = &data.profile;
+    print_message(&mut messages, format!("Hello {}! {}", profile.name, profile.intro), Category::Story);
+
+    (state, messages)
+}
+
+/// Offers to continue the saved game if there is one, otherwise starts a new game. New games
+/// come with a replay recording them. Returns `None` if the player backed out of the menu.
+fn load_or_new_game(root: &mut Root, bindings: &KeyBindings, data: &Rc<GameData>, options: GameOptions) -> Option<(GameState, Messages, Option<Replay>)> {
+    let seed = arg_value("--seed").map(|seed| seed.parse().expect("--seed expects a non-negative integer"));
+    if seed.is_none() && save_exists(SAVE_FILE) {
+        match menu(root, bindings, "Your adventure awaits.", &["Continue", "New game"]) {
+            Some(0) => match load_game(SAVE_FILE, data.clone()) {
+                Ok((state, messages)) => {
+                    // a continued game didn't start from its seed, so it can't be replayed
+                    println!("continuing seed {}", state.seed);
+                    return Some((state, messages, None));
+                },
+                Err(e) => println!("couldn't load {}: {}", SAVE_FILE, e),
+            },
+            Some(_) => if let Err(e) = delete_save(SAVE_FILE) {
+                println!("couldn't delete {}: {}", SAVE_FILE, e);
+            },
+            None => return None,
         }
     }
 
-    pub fn clear(&self, con: &mut Console) {
-        for y in 0..(self.height - 1) {
-            for x in 0..(self.width - 1) {
-                let is_wall = self.get(x, y).is_wall();
-                if is_wall {
-                    con.put_char(x, y, ' ', BackgroundFlag::None);
-                }
+    let seed = seed.unwrap_or_else(rand::random);
+    let (state, messages) = new_game(seed, options, data);
+    Some((state, messages, Some(Replay::new(seed, options))))
+}
+
+/// Shows `options` as a list the player can move through with the up and down keys and pick
+/// with Start. Returns `None` if they exited or closed the window.
+fn menu(root: &mut Root, bindings: &KeyBindings, header: &str, options: &[&str]) -> Option<usize> {
+    let mut selected = 0;
+    while !root.window_closed() {
+        root.set_default_background(colors::BLACK);
+        root.clear();
+        root.set_default_foreground(colors::CYAN);
+        root.print_rect_ex(SCREEN_WIDTH / 2, SCREEN_HEIGHT / 3, SCREEN_WIDTH - 2, 0, BackgroundFlag::None, TextAlignment::Center, header);
+        for (i, option) in options.iter().enumerate() {
+            let y = SCREEN_HEIGHT / 2 + i as i32 * 2;
+            if i == selected {
+                root.set_default_foreground(colors::WHITE);
+                root.print_ex(SCREEN_WIDTH / 2, y, BackgroundFlag::None, TextAlignment::Center, format!("> {} <", option));
+            } else {
+                root.set_default_foreground(colors::GREY);
+                root.print_ex(SCREEN_WIDTH / 2, y, BackgroundFlag::None, TextAlignment::Center, *option);
             }
         }
-    }
-
-    pub fn set_rect(&mut self, rect: Rect, tile: Tile, inclusive: bool) {
-        let initial_add = if inclusive { 0 } else { 1 };
-        let after_add = if inclusive { 1 } else { 0 };
-        for x in std::cmp::max(0, rect.x1 + initial_add)..std::cmp::min(self.width - 1, rect.x2 + after_add) {
-            for y in std::cmp::max(0, rect.y1 + initial_add)..std::cmp::min(self.height - 1, rect.y2 + after_add) {
-                self.set(x, y, tile);
+        root.flush();
+
+        let key = root.wait_for_keypress(true);
+        if key.pressed {
+            match bindings.command(&key_name(key)) {
+                Some(Command::MoveUp) => selected = (selected + options.len() - 1) % options.len(),
+                Some(Command::MoveDown) => selected = (selected + 1) % options.len(),
+                Some(Command::Start) => {
+                    root.clear();
+                    return Some(selected);
+                },
+                Some(Command::Exit) => return None,
+                _ => {},
             }
         }
     }
+    None
+}
 
-    pub fn set_tunnel(&mut self, start: (i32, i32), end: (i32, i32), radius: i32, tile: Tile) {
-        for (x,y) in Bresenham::new((start.0 as isize, start.1 as isize), (end.0 as isize, end.1 as isize)) {
-            self.set_rect(Rect::new(x as i32 - radius, y as i32 - radius, radius*2, radius*2), tile, true);
-        }
+/// Asks which piece of equipment to put on or take off. Returns its inventory slot.
+fn choose_equipment(root: &mut Root, bindings: &KeyBindings, state: &GameState, messages: &mut Messages) -> Option<usize> {
+    if state.stage != GameStage::Playing {
+        return None;
     }
+    let slots = state.inventory.equipment(&state.world);
+    if slots.is_empty() {
+        print_message(messages, "You have nothing to equip.", Category::System);
+        return None;
+    }
+    let names: Vec<String> = slots.iter().map(|&i| item::label(&state.world, state.inventory.items[i])).collect();
+    let options: Vec<&str> = names.iter().map(String::as_str).collect();
+    menu(root, bindings, "Equip or take off what?", &options).map(|choice| slots[choice])
 }
 
-#[derive(Clone, Copy, Debug)]
-struct Rect {
-    x1: i32,
-    x2: i32,
-    y1: i32,
-    y2: i32,
+/// Asks which stat to raise with a level up.
+fn choose_stat(root: &mut Root, bindings: &KeyBindings, state: &GameState) -> Option<Stat> {
+    let f = *state.world.fighters.get(state.player)?;
+    let options = [
+        format!("+20 max HP (now {})", f.max_hp),
+        format!("+1 power (now {})", f.power),
+        format!("+1 defense (now {})", f.defense),
+    ];
+    let options: Vec<&str> = options.iter().map(String::as_str).collect();
+    let header = format!("Level up! Pick what to raise at level {}:", state.player_level + 1);
+    match window(root, bindings, &header, &options) {
+        Some(0) => Some(Stat::MaxHp),
+        Some(1) => Some(Stat::Power),
+        Some(2) => Some(Stat::Defense),
+        _ => None,
+    }
 }
 
-impl Rect {
-    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
-        Rect { x1: x, y1: y, x2: x + w, y2: y + h }
+/// Shows the inventory over the map, then what can be done with the item picked from it.
+/// Returns the action to take, or `None` if the player backed out.
+fn open_inventory(root: &mut Root, bindings: &KeyBindings, state: &GameState, messages: &mut Messages) -> Option<Action> {
+    if state.stage != GameStage::Playing {
+        return None;
     }
-
-    pub fn center(&self) -> (i32, i32) {
-        let center_x = (self.x1 + self.x2) / 2;
-        let center_y = (self.y1 + self.y2) / 2;
-        (center_x, center_y)
+    let items = &state.inventory.items;
+    if items.is_empty() {
+        print_message(messages, "Your pack is empty.", Category::System);
+        return None;
     }
-
-    pub fn intersects_with(&self, other: &Rect) -> bool {
-        // returns true if this rectangle intersects with another one
-        (self.x1 <= other.x2) && (self.x2 >= other.x1) &&
-            (self.y1 <= other.y2) && (self.y2 >= other.y1)
+    let header = format!("Inventory ({}/{}), {} gold", items.len(), INVENTORY_CAPACITY, state.gold);
+    let labels: Vec<String> = items.iter().map(|&id| item::label(&state.world, id)).collect();
+    let options: Vec<&str> = labels.iter().map(String::as_str).collect();
+    loop {
+        let index = window(root, bindings, &header, &options)?;
+        let id = items[index];
+        let name = state.world.name(id);
+        let needs_target = state.world.items.get(id).is_some_and(|i| i.effect.needs_target());
+        match window(root, bindings, &labels[index], &["Use", "Drop", "Throw", "Inspect"]) {
+            Some(0) if needs_target => {
+                let header = format!("Aim the {}", name);
+                if let Some((x, y)) = choose_target(root, bindings, state, &header) {
+                    return Some(Action::UseItemAt(index, x, y));
+                }
+            },
+            Some(0) => return Some(Action::UseItem(index)),
+            Some(1) => return Some(Action::DropItem(index)),
+            Some(2) => {
+                let question = format!("Throw the {} which way?", name);
+                if let Some(Action::Move(dx, dy)) = prompt(root, bindings, &question).and_then(Command::action) {
+                    return Some(Action::ThrowItem(index, dx, dy));
+                }
+            },
+            Some(_) => {
+                prompt(root, bindings, &item::describe(&state.data, &state.world, id));
+            },
+            None => {},
+        }
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-enum GameStage {
-    Title,
-    Playing,
-    GameOver,
-    Won,
-}
-
-struct GameState {
-    player: Entity,
-    npcs: Vec<Entity>,
-    objects: Vec<Entity>,
-    map: Map,
-    camera_pos: (i32, i32),
-    fov_map: FovMap,
-    prev_player_pos: (i32, i32),
-    stage: GameStage,
-    recent_enemy_id: Option<usize>,
-    inventory: Inventory,
-}
-
-fn open_chest(messages: &mut Messages, stage: &mut GameStage) {
-    print_message(messages, "It sounds like the chest is opening... Congratulations, you win!", colors::CYAN);
-    print_message(messages, "Press START to open your gift!", colors::LIGHT_MAGENTA);
-
-    *stage = GameStage::Won;
-    Command::new("sh")
-        .arg("-c")
-        .arg("/home/pi/clean_startup.sh")
-        .spawn()
-        .expect("failed to clean startup");
+/// Lets the player move a cursor over the map with the movement keys and pick a tile they can
+/// see with Start. Returns `None` if they backed out.
+fn choose_target(root: &mut Root, bindings: &KeyBindings, state: &GameState, header: &str) -> Option<(i32, i32)> {
+    let start = state.closest_visible_npc()
+        .and_then(|id| state.world.position(id))
+        .map_or(state.player_pos(), |pos| pos.xy());
+    move_cursor(root, bindings, state, start, |root, (x, y), (sx, sy)| {
+        let visible = state.fov_map.is_in_fov(x, y);
+        let color = if visible { colors::WHITE } else { colors::RED };
+        root.set_char_background(sx, sy, color, BackgroundFlag::Set);
+        root.set_default_foreground(colors::WHITE);
+        root.print_ex(SCREEN_WIDTH / 2, 0, BackgroundFlag::None, TextAlignment::Center, header);
+        visible
+    })
 }
 
-fn main() {
-    let mut root = Root::initializer()
-        .font("dejavu10x10_gs_tc.png", FontLayout::Tcod)
-        .font_type(FontType::Greyscale)
-        .size(SCREEN_WIDTH, SCREEN_HEIGHT)
-        .title("Rust/libtcod tutorial")
-        .init();
-
-    let mut con = Offscreen::new(MAP_WIDTH, MAP_HEIGHT);
-
-    let mut status = Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT);
-
-    tcod::system::set_fps(LIMIT_FPS);
-    tcod::input::show_cursor(false);
-
-
-    let (initial_map, rooms, (px, py)) = generate_map(MAP_WIDTH-SCREEN_WIDTH-1, MAP_HEIGHT-SCREEN_HEIGHT-1);
-    let (px, py) = (px + SCREEN_WIDTH/2, py + SCREEN_HEIGHT/2);
-
-    let mut final_map = Map::new(MAP_WIDTH, MAP_HEIGHT, Tile::wall());
-
-    for y in 0..MAP_HEIGHT-SCREEN_HEIGHT-1 {
-        for x in 0..MAP_WIDTH-SCREEN_WIDTH-1 {
-            final_map.set(x+SCREEN_WIDTH/2, y+SCREEN_HEIGHT/2, initial_map.get(x, y));
+/// Lets the player move a cursor over the map, with whatever is under it described in the panel,
+/// until they press Exit or Start.
+fn look(root: &mut Root, bindings: &KeyBindings, state: &GameState) {
+    move_cursor(root, bindings, state, state.player_pos(), |root, pos, (sx, sy)| {
+        root.set_char_background(sx, sy, colors::LIGHT_YELLOW, BackgroundFlag::Set);
+        root.set_default_background(colors::BLACK);
+        root.rect(0, PANEL_Y, SCREEN_WIDTH, PANEL_HEIGHT, true, BackgroundFlag::Set);
+        let mut y = PANEL_Y;
+        for (i, line) in state.look_at(pos).iter().enumerate() {
+            if y >= SCREEN_HEIGHT {
+                break;
+            }
+            root.set_default_foreground(if i == 0 { colors::WHITE } else { colors::LIGHT_GREY });
+            root.print_rect(MSG_X, y, MSG_WIDTH, SCREEN_HEIGHT - y, line);
+            y += root.get_height_rect(MSG_X, y, MSG_WIDTH, SCREEN_HEIGHT - y, line);
         }
-    }
-
-    let npcs = generate_monsters(&rooms[1..], &mut final_map);
-    let objects = generate_objects(&rooms[..], &mut final_map);
-    let mut player = Entity::new(px, py, '@', colors::WHITE, "James", &mut final_map, true, true).unwrap();
-    player.fighter = Some(Fighter{
-        max_hp: 30,
-        hp: 30,
-        defense: 2,
-        power: 5,
-        on_death: DeathCallback::Player,
+        true
     });
+}
 
-    let mut state = GameState {
-        player,
-        npcs,
-        objects,
-        map: final_map,
-        camera_pos: (px, py),
-        fov_map: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
-        prev_player_pos: (px, py),
-        stage: GameStage::Title,
-        recent_enemy_id: None,
-        inventory: Inventory { healing_potions: 0, has_key: false },
-    };
-
-    let mut messages: Messages = vec![];
+/// Moves a cursor over the map with the movement keys, starting at `start`, until Start or Exit
+/// is pressed. `draw` is called with the cursor's map and screen positions every time it moves,
+/// on top of what was on screen before, and returns whether Start may pick that tile. Returns
+/// the tile Start was pressed on.
+fn move_cursor<F>(root: &mut Root, bindings: &KeyBindings, state: &GameState, start: (i32, i32), mut draw: F) -> Option<(i32, i32)>
+    where F: FnMut(&mut Root, (i32, i32), (i32, i32)) -> bool
+{
+    let behind = save_screen(root);
+    // where the map was blitted from in render_all
+    let offset = (state.camera_pos.0 - SCREEN_WIDTH / 2, state.camera_pos.1 - SCREEN_HEIGHT / 2);
+    let mut cursor = start;
 
-    print_message(&mut messages, "Hello James! Find the key in the Tomb of the Ancient King and bring it back here to unluck the box... or perish. Press Start to Begin!", colors::CYAN);
+    let mut picked = None;
+    while !root.window_closed() {
+        blit(&behind, (0, 0), (SCREEN_WIDTH, SCREEN_HEIGHT), root, (0, 0), 1.0, 1.0);
+        let allowed = draw(root, cursor, (cursor.0 - offset.0, cursor.1 - offset.1));
+        root.flush();
 
-    // compute initial fov
-    for y in 0..MAP_HEIGHT-1 {
-        for x in 0..MAP_WIDTH {
-            state.fov_map.set(x, y,
-                !state.map.get(x, y).blocks_sight,
-                !state.map.get(x, y).blocks_movement
-            );
+        let key = root.wait_for_keypress(true);
+        if !key.pressed {
+            continue;
         }
-    }
-    compute_fov(&mut state, true);
-
-    // Render initial state
-    render_all(&mut root, &mut con, &mut status, &state, true, &mut messages);
-
-    // Loop
-    while !root.window_closed() {
-        // handle keys and exit game if needed
-        let action = handle_keys(&mut root, &mut con, &mut status, &mut state, &mut messages);
-        match action {
-            PlayerAction::Exit => break,
-            PlayerAction::TookTurn => {
-                handle_camera(&mut state);
-                let fov_recmputed = compute_fov(&mut state, false);
-                for id in 0..state.npcs.len() {
-                    if state.npcs[id].ai.is_some() {
-                        Ai::take_turn(id, &mut state, &mut messages);
-                    }
-                }
-                let mut tbr = Vec::new();
-                for (i, obj) in state.objects.iter().enumerate() {
-                    if obj.x == state.player.x && obj.y == state.player.y {
-                        match obj.item {
-                            Some(item) => {
-                                match item {
-                                    Item::Heal => {
-                                        state.inventory.healing_potions += 1;
-                                        print_message(&mut messages, "You picked up a health potion!", colors::CHARTREUSE)
-                                    },
-                                    Item::Key => {
-                                        state.inventory.has_key = true;
-                                        print_message(&mut messages, "You picked up the key!", colors::CHARTREUSE)
-                                    }
-                                }
-                                tbr.push(i);
-                            },
-                            None => {}
-                        }
-                    }
-                }
-                for i in tbr {
-                    state.objects.remove(i);
-                }
-                render_all(&mut root, &mut con, &mut status, &mut state, fov_recmputed, &mut messages);
-                state.prev_player_pos = (state.player.x, state.player.y);
+        match bindings.command(&key_name(key)) {
+            Some(Command::Start) if allowed => {
+                picked = Some(cursor);
+                break;
             },
-            PlayerAction::DidntTakeTurn => {}
-        }
-        if !state.player.alive {
-            println!("Game over");
-            state.stage = GameStage::GameOver;
-        }
-    }
-}
-
-fn generate_objects(rooms: &[Rect], map: &mut Map) -> Vec<Entity> {
-    let mut objects = Vec::new();
-    let start = rooms[0].center();
-    let mut furthest_room: Rect = rooms[0];
-    let mut furthest_dist = 0;
-    let mut rng = rand::thread_rng();
-    for room in rooms {
-        let num_items = rand::thread_rng().gen_range(0, MAX_ROOM_ITEMS + 1);
-
-        for _ in 0..num_items {
-            // only place it if the tile is not blocked
-            let mut i = 0;
-            loop {
-                let x = rng.gen_range(room.x1 + 1, room.x2);
-                let y = rng.gen_range(room.y1 + 1, room.y2);
-                let object = Entity::new(x + SCREEN_WIDTH / 2, y + SCREEN_HEIGHT / 2, '^', colors::LIGHT_CYAN, "healing potion", map, false, false);
-                match object {
-                    Some(mut m) => {
-                        m.item = Some(Item::Heal);
-                        objects.push(m);
-                        break;
-                    },
-                    None => {
-                        i += 1;
-                        if i > 40 {
-                            break;
-                        }
-                    },
+            Some(Command::Exit) => break,
+            Some(command) => if let Some(Action::Move(dx, dy)) = command.action() {
+                let (x, y) = (cursor.0 + dx - offset.0, cursor.1 + dy - offset.1);
+                if x >= 0 && y >= 0 && x < SCREEN_WIDTH && y < PANEL_Y {
+                    cursor = (cursor.0 + dx, cursor.1 + dy);
                 }
-            }
+            },
+            None => {},
         }
+    }
+    blit(&behind, (0, 0), (SCREEN_WIDTH, SCREEN_HEIGHT), root, (0, 0), 1.0, 1.0);
+    picked
+}
 
-        let center = room.center();
-        let dist = (center.0 - start.0).pow(2) + (center.1 - start.1).pow(2);
-        if dist > furthest_dist {
-            furthest_room = room.clone();
-            furthest_dist = dist;
+/// Like `menu`, but drawn in a box over whatever is on screen, which is put back once it closes.
+fn window(root: &mut Root, bindings: &KeyBindings, header: &str, options: &[&str]) -> Option<usize> {
+    let behind = save_screen(root);
+    let mut selected = 0;
+    let mut choice = None;
+    while !root.window_closed() {
+        draw_window(root, header, options, selected);
+        let key = root.wait_for_keypress(true);
+        if key.pressed {
+            match bindings.command(&key_name(key)) {
+                Some(Command::MoveUp) => selected = (selected + options.len() - 1) % options.len(),
+                Some(Command::MoveDown) => selected = (selected + 1) % options.len(),
+                Some(Command::Start) => {
+                    choice = Some(selected);
+                    break;
+                },
+                Some(Command::Exit) => break,
+                _ => {},
+            }
         }
     }
+    blit(&behind, (0, 0), (SCREEN_WIDTH, SCREEN_HEIGHT), root, (0, 0), 1.0, 1.0);
+    choice
+}
 
-    let mut i = 0;
-    loop {
-        let x = rng.gen_range(furthest_room.x1 + 1, furthest_room.x2);
-        let y = rng.gen_range(furthest_room.y1 + 1, furthest_room.y2);
-        let object = Entity::new(x + SCREEN_WIDTH / 2, y + SCREEN_HEIGHT / 2, '!', colors::GOLD, "key", map, false, false);
-        match object {
-            Some(mut m) => {
-                m.item = Some(Item::Key);
-                objects.push(m);
+/// Shows `text` in a box over whatever is on screen until a bound key is pressed. Returns its
+/// command, or `None` if it was Exit.
+fn prompt(root: &mut Root, bindings: &KeyBindings, text: &str) -> Option<Command> {
+    let behind = save_screen(root);
+    let mut command = None;
+    while !root.window_closed() {
+        draw_window(root, text, &[], 0);
+        let key = root.wait_for_keypress(true);
+        if key.pressed {
+            command = bindings.command(&key_name(key));
+            if command.is_some() {
                 break;
-            },
-            None => {
-                i += 1;
-                if i > 40 {
-                    break;
-                }
-            },
+            }
         }
     }
-    let x = start.0;
-    let y = start.1 - 1;
-    let mut chest = Entity::new(x + SCREEN_WIDTH / 2, y + SCREEN_HEIGHT / 2, '&', colors::DARK_AMBER, "chest", map, true, false).unwrap();
-    chest.chest = Some(Chest);
-    objects.push(chest);
+    blit(&behind, (0, 0), (SCREEN_WIDTH, SCREEN_HEIGHT), root, (0, 0), 1.0, 1.0);
+    match command {
+        Some(Command::Exit) => None,
+        command => command,
+    }
+}
 
-    objects
+fn save_screen(root: &Root) -> Offscreen {
+    let mut screen = Offscreen::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    blit(root, (0, 0), (SCREEN_WIDTH, SCREEN_HEIGHT), &mut screen, (0, 0), 1.0, 1.0);
+    screen
 }
 
-fn generate_monsters(rooms: &[Rect], map: &mut Map) -> Vec<Entity> {
-    let mut npcs: Vec<Entity> = Vec::new();
-    let mut rng = rand::thread_rng();
-    for room in rooms {
-        let mut num_monsters = rng.gen_range(0, MAX_ROOM_MONSTERS + 1);
-        if num_monsters == 0 {
-            if rng.gen() {
-                num_monsters = 1;
-            }
+fn draw_window(root: &mut Root, header: &str, options: &[&str], selected: usize) {
+    let header_height = root.get_height_rect(0, 0, WINDOW_WIDTH - 2, SCREEN_HEIGHT, header);
+    let height = header_height + options.len() as i32 + if options.is_empty() { 2 } else { 3 };
+    let mut window = Offscreen::new(WINDOW_WIDTH, height);
+    window.set_default_foreground(colors::CYAN);
+    window.print_frame(0, 0, WINDOW_WIDTH, height, true, BackgroundFlag::Set, None::<&str>);
+    window.print_rect(1, 1, WINDOW_WIDTH - 2, 0, header);
+    for (i, option) in options.iter().enumerate() {
+        if i == selected {
+            window.set_default_foreground(colors::WHITE);
+            window.print(1, header_height + 2 + i as i32, format!("> {}", option));
+        } else {
+            window.set_default_foreground(colors::GREY);
+            window.print(1, header_height + 2 + i as i32, format!("  {}", option));
         }
-        for _ in 0..num_monsters {
-            loop {
-                let x = rng.gen_range(room.x1 + 1, room.x2);
-                let y = rng.gen_range(room.y1 + 1, room.y2);
-                let monster = if rand::random::<f32>() < 0.8 {
-                    Entity::new(x + SCREEN_WIDTH/2, y + SCREEN_HEIGHT/2, 'g', colors::DESATURATED_GREEN, "Goblin", map, true, true)
-                } else {
-                    Entity::new(x + SCREEN_WIDTH/2, y + SCREEN_HEIGHT/2, 'T', colors::DARK_GREEN, "Troll", map, true, true)
-                };
-                match monster {
-                    Some(mut m) => {
-                        if m.char == 'g' {
-                            m.fighter = Some(Fighter{max_hp: 10, hp: 10, defense: 0, power: 3, on_death: DeathCallback::Monster});
-                            m.ai = Some(Ai);
-                        } else if m.char == 'T' {
-                            m.fighter = Some(Fighter{max_hp: 16, hp: 16, defense: 1, power: 4, on_death: DeathCallback::Monster});
-                            m.ai = Some(Ai);
-                        }
-                        npcs.push(m);
-                        break;
+    }
+    let (x, y) = ((SCREEN_WIDTH - WINDOW_WIDTH) / 2, (SCREEN_HEIGHT - height) / 2);
+    blit(&window, (0, 0), (WINDOW_WIDTH, height), root, (x, y), 1.0, 0.9);
+    root.flush();
+}
+
+/// Shows the whole message log, each message with the turn it was printed on, starting from the
+/// newest. Scrolls a message at a time with the up and down keys, and left and right cycle
+/// between showing every message and only those of one category, until Exit or History is pressed.
+fn show_history(root: &mut Root, bindings: &KeyBindings, messages: &Messages, profile: &Profile) {
+    const TURN_WIDTH: i32 = 5;
+    let text_width = SCREEN_WIDTH - TURN_WIDTH - 1;
+    // 0 shows every message, anything else only those under FILTERS[filter - 1]
+    let mut filter = 0;
+
+    while !root.window_closed() {
+        let log: Vec<&Message> = messages.history()
+            .filter(|msg| filter == 0 || msg.category.filter() == FILTERS[filter - 1])
+            .collect();
+        let lines: Vec<String> = log.iter().map(|msg| msg.to_string()).collect();
+        let heights: Vec<i32> = lines.iter().map(|line| root.get_height_rect(TURN_WIDTH, 0, text_width, 0, line)).collect();
+
+        // the furthest it can scroll down, with the newest message at the bottom of the screen
+        let mut last_top = log.len();
+        let mut used = 0;
+        while last_top > 0 && used + heights[last_top - 1] <= SCREEN_HEIGHT - 2 {
+            used += heights[last_top - 1];
+            last_top -= 1;
+        }
+        // a message too long for the screen is still shown, cut off
+        let last_top = std::cmp::min(last_top, log.len().saturating_sub(1));
+        let mut top = last_top;
+
+        let title = if filter == 0 {
+            "Message history".to_string()
+        } else {
+            format!("Message history: {}", FILTERS[filter - 1].name())
+        };
+        let old_filter = filter;
+        while filter == old_filter && !root.window_closed() {
+            root.set_default_background(colors::BLACK);
+            root.clear();
+            root.set_default_foreground(colors::CYAN);
+            root.print_ex(SCREEN_WIDTH / 2, 0, BackgroundFlag::None, TextAlignment::Center, &title);
+            let mut y = 2;
+            for ((msg, line), &h) in log[top..].iter().zip(&lines[top..]).zip(&heights[top..]) {
+                if y + h > SCREEN_HEIGHT && y > 2 {
+                    break;
+                }
+                root.set_default_foreground(colors::DARK_GREY);
+                root.print_ex(TURN_WIDTH - 1, y, BackgroundFlag::None, TextAlignment::Right, msg.turn.to_string());
+                root.set_default_foreground(msg.category.color(profile));
+                root.print_rect(TURN_WIDTH, y, text_width, 0, line);
+                y += h;
+            }
+            root.flush();
+
+            let key = root.wait_for_keypress(true);
+            if key.pressed {
+                match bindings.command(&key_name(key)) {
+                    Some(Command::MoveUp) if top > 0 => top -= 1,
+                    Some(Command::MoveDown) if top < last_top => top += 1,
+                    Some(Command::MoveLeft) => filter = (filter + FILTERS.len()) % (FILTERS.len() + 1),
+                    Some(Command::MoveRight) => filter = (filter + 1) % (FILTERS.len() + 1),
+                    Some(Command::Exit) | Some(Command::History) => {
+                        root.clear();
+                        return;
                     },
-                    None => continue,
+                    _ => {},
                 }
             }
-
         }
     }
-    npcs
+    root.clear();
 }
 
-fn generate_map(width: i32, height: i32) -> (Map, Vec<Rect>, (i32, i32)) {
-    let mut map: Map;
-    let mut starting_position = (0, 0);
-    let mut rooms: Vec<Rect>;
-    loop {
-        map = Map::new(width, height, Tile::wall());
-        rooms = Vec::new();
-
-        for _ in 0..MAX_ROOMS {
-            let mut rng = rand::thread_rng();
-            let w = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-            let h = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-            let x = rng.gen_range(0, map.width - w);
-            let y = rng.gen_range(0, map.height - h);
-            let new_room = Rect::new(x, y, w, h);
-
-            let failed = rooms.iter().any(|other_room| new_room.intersects_with(other_room));
-            if !failed {
-                map.set_rect(new_room, Tile::empty(), false);
-                let (nx, ny) = new_room.center();
-                if rooms.is_empty() {
-                    starting_position = (nx, ny);
+/// Reads the value following `name` on the command line, e.g. `--seed 12345`.
+fn arg_value(name: &str) -> Option<String> {
+    let mut args = std::env::args().skip_while(|arg| arg != name);
+    args.next().map(|_| args.next().unwrap_or_else(|| panic!("{} expects a value", name)))
+}
+
+fn draw_map(con: &mut dyn Console, map: &Map, player_pos: (i32, i32), fov_map: &FovMap) {
+    for y in 0..(map.height - 1) {
+        for x in 0..(map.width - 1) {
+            if map.get(x, y).explored {
+                let visible = fov_map.is_in_fov(x, y);
+                let is_wall = map.get(x, y).is_wall();
+                let col = match (visible, is_wall) {
+                    (false, true) => COLOR_WALL_DARK,
+                    (false, false) => COLOR_GROUND_DARK,
+                    (true, true) => colors::lerp(COLOR_WALL_LIGHT, COLOR_WALL_DARK, ((((x - player_pos.0).pow(2) + (y - player_pos.1).pow(2)) as f32).sqrt() / TORCH_RADIUS as f32).powi(2)),
+                    (true, false) => colors::lerp(COLOR_GROUND_LIGHT, COLOR_GROUND_DARK, ((((x - player_pos.0).pow(2) + (y - player_pos.1).pow(2)) as f32).sqrt() / TORCH_RADIUS as f32).powi(2)),
+                };
+                if is_wall {
+                    con.put_char_ex(x, y, '#',
+                                    Color {
+                                        r: std::cmp::max(col.r as i16 - 8, 0) as u8,
+                                        g: std::cmp::max(col.g as i16 - 8, 0) as u8,
+                                        b: std::cmp::max(col.b as i16 - 8, 0) as u8,
+                                    },
+                                    col);
                 } else {
-                    let (px, py) = rooms[rooms.len() - 1].center();
-
-                    if rng.gen_range(0, 100) <= 10 {
-                        map.set_tunnel((px, py), (nx, ny), 1, Tile::empty());
-                    } else if rng.gen() {
-                        map.set_rect(Rect{x1: std::cmp::min(px, nx), y1: py, x2: std::cmp::max(nx, px), y2: py}, Tile::empty(), true);
-                        map.set_rect(Rect{x1: nx, y1: std::cmp::min(py, ny), x2: nx, y2: std::cmp::max(ny, py)}, Tile::empty(), true);
-                    } else {
-                        map.set_rect(Rect{x1: std::cmp::min(px, nx), y1: py, x2: std::cmp::max(nx, px), y2: py}, Tile::empty(), true);
-                        map.set_rect(Rect{x1: nx, y1: std::cmp::min(py, ny), x2: nx, y2: std::cmp::max(ny, py)}, Tile::empty(), true);
-                    }
+                    con.set_char_background(x, y, col, BackgroundFlag::Set);
                 }
-                rooms.push(new_room);
             }
         }
-        let total = width * height; 
-        let mut full = 0;
-        for y in 0..height-1 {
-            for x in 0..width-1 {
-                if map.get(x,y).is_wall() {
-                    full += 1;
-                }
+    }
+}
+
+fn clear_map(con: &mut dyn Console, map: &Map) {
+    for y in 0..(map.height - 1) {
+        for x in 0..(map.width - 1) {
+            let is_wall = map.get(x, y).is_wall();
+            if is_wall {
+                con.put_char(x, y, ' ', BackgroundFlag::None);
             }
         }
-        let percent = full as f64 / total as f64;
-        if percent <= 0.6 && percent >= 0.4 {
-            break;
-        }
     }
-
-    (map, rooms, starting_position)
 }
 
-fn handle_camera(state: &mut GameState) {
-    if state.player.x - state.camera_pos.0 < -1 {
-        state.camera_pos.0 -= 1
-    } else if state.player.x - state.camera_pos.0 > 0 {
-        state.camera_pos.0 += 1
-    }
-    if state.player.y - state.camera_pos.1 < -1 {
-        state.camera_pos.1 -= 1
-    } else if state.player.y - state.camera_pos.1 > 0 {
-        state.camera_pos.1 += 1
+fn draw_entity(con: &mut dyn Console, state: &GameState, id: EntityId) {
+    if let (Some(pos), Some(glyph)) = (state.world.position(id), state.world.glyphs.get(id)) {
+        con.set_default_foreground(glyph.color);
+        con.put_char(pos.x, pos.y, glyph.char, BackgroundFlag::None);
     }
 }
 
-fn compute_fov(state: &mut GameState, force: bool) -> bool {
-    if force || state.prev_player_pos != (state.player.x, state.player.y) {
-        state.fov_map.compute_fov(state.player.x, state.player.y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
-        for y in 0..(state.map.height - 1) {
-            for x in 0..(state.map.width - 1) {
-                let visible = state.fov_map.is_in_fov(x, y);
-                let explored = &mut state.map.get_mut(x, y).explored;
-                if visible {
-                    *explored = true;
-                }
-            }
-        }
-        if let Some(id) = state.recent_enemy_id {
-            let enemy = &mut state.npcs[id];
-            let visible = state.fov_map.is_in_fov(enemy.x, enemy.y);
-            if !visible {
-                state.recent_enemy_id = None;
-            }
-        }
-        true
-    } else {
-        false
+fn clear_entity(con: &mut dyn Console, state: &GameState, id: EntityId) {
+    if let Some(pos) = state.world.position(id) {
+        con.put_char(pos.x, pos.y, ' ', BackgroundFlag::None);
     }
 }
 
-fn render_all(root: &mut Root, con: &mut Offscreen, panel: &mut Offscreen, state: &GameState, rerender_map: bool, messages: &mut Messages) {
+fn render_all(tcod: &mut Tcod, state: &GameState, rerender_map: bool, messages: &Messages) {
+    let Tcod { ref mut root, ref mut con, ref mut panel } = *tcod;
+    let level = state.level();
+    let world = &state.world;
     if rerender_map {
-        state.map.clear(con);
-        state.map.draw(con, (state.player.x, state.player.y), &state.fov_map);
-    }
-    let mut to_draw: Vec<_> = state.npcs.iter().filter(|o| state.fov_map.is_in_fov(o.x, o.y)).collect();
-    for obj in state.objects.iter().filter(|o| state.fov_map.is_in_fov(o.x, o.y)) {
-        obj.draw(con);
+        clear_map(con, &level.map);
+        draw_map(con, &level.map, state.player_pos(), &state.fov_map);
     }
-    to_draw.sort_by(|o1, o2| { o1.blocks.cmp(&o2.blocks) });
-    for object in &to_draw {
-        object.draw(con);
+    let mut to_draw: Vec<EntityId> = world.on_level(state.depth).into_iter()
+        .filter(|&id| world.position(id).is_some_and(|pos| state.fov_map.is_in_fov(pos.x, pos.y)))
+        .collect();
+    // what can be stood on goes under what's standing on it, and the player on top of everything
+    to_draw.sort_by_key(|&id| (world.blocks.has(id), id == state.player));
+    for &id in &to_draw {
+        draw_entity(con, state, id);
     }
-    state.player.draw(con);
     blit(con, (state.camera_pos.0 - SCREEN_WIDTH / 2, state.camera_pos.1 - SCREEN_HEIGHT / 2), (SCREEN_WIDTH, SCREEN_HEIGHT), root, (0, 0), 1.0, 1.0);
 
     // prepare to render the GUI panel
@@ -830,28 +824,36 @@ fn render_all(root: &mut Root, con: &mut Offscreen, panel: &mut Offscreen, state
     panel.clear();
 
     // show the player's stats
-    let hp = state.player.fighter.map_or(0, |f| f.hp);
-    let max_hp = state.player.fighter.map_or(0, |f| f.max_hp);
-    render_bar(panel, 0, 0, BAR_WIDTH, "HP", hp, max_hp, colors::LIGHT_RED, colors::DARKER_RED);
-
-    // how recent enemy's state
-    if let Some(id) = state.recent_enemy_id {
-        let enemy = &state.npcs[id];
-        let hp = enemy.fighter.map_or(0, |f| f.hp);
-        let max_hp = enemy.fighter.map_or(0, |f| f.max_hp);
-        render_bar(panel, BAR_WIDTH, 0, SCREEN_WIDTH-BAR_WIDTH, format!("{}",enemy.name), hp, max_hp, colors::LIGHT_GREEN, colors::DARKER_GREEN);
+    let player = world.fighters.get(state.player);
+    let hp = player.map_or(0, |f| f.hp);
+    let max_hp = player.map_or(0, |f| f.max_hp());
+    render_bar(panel, 0, 0, BAR_WIDTH - DEPTH_WIDTH, "HP", hp, max_hp, colors::LIGHT_RED, colors::DARKER_RED);
+    panel.set_default_foreground(colors::LIGHT_GREY);
+    panel.print_ex(BAR_WIDTH - DEPTH_WIDTH / 2, 0, BackgroundFlag::None, TextAlignment::Center,
+                   format!("D{}", state.depth + 1));
+    let xp = player.map_or(0, |f| f.xp);
+    render_bar(panel, BAR_WIDTH, 0, BAR_WIDTH - DEPTH_WIDTH, "XP", xp, state.xp_to_level_up(), colors::LIGHT_VIOLET, colors::DARKER_VIOLET);
+    panel.set_default_foreground(colors::LIGHT_GREY);
+    panel.print_ex(2 * BAR_WIDTH - DEPTH_WIDTH / 2, 0, BackgroundFlag::None, TextAlignment::Center,
+                   format!("L{}", state.player_level));
+
+    // how recent enemy's state, on the row below the player's
+    if let Some(enemy) = state.recent_enemy.and_then(|id| world.fighters.get(id).map(|f| (id, f))) {
+        let (id, f) = enemy;
+        render_bar(panel, 0, 1, SCREEN_WIDTH, world.name(id), f.hp, f.max_hp(), colors::LIGHT_GREEN, colors::DARKER_GREEN);
     }
 
     // print the game messages, one line at a time
-    let mut y = MSG_HEIGHT as i32;
-    for &(ref msg, color) in messages.iter().rev() {
-        let msg_height = panel.get_height_rect(MSG_X, 0, MSG_WIDTH, MSG_HEIGHT as i32, msg);
+    let mut y = PANEL_HEIGHT;
+    for msg in messages.recent().rev() {
+        let text = msg.to_string();
+        let msg_height = panel.get_height_rect(MSG_X, 0, MSG_WIDTH, MSG_HEIGHT as i32, &text);
         y -= msg_height;
-        if y < 1 {
+        if y < MSG_Y {
             break;
         }
-        panel.set_default_foreground(color);
-        panel.print_rect(MSG_X, y, MSG_WIDTH, 0, msg);
+        panel.set_default_foreground(msg.category.color(&state.data.profile));
+        panel.print_rect(MSG_X, y, MSG_WIDTH, 0, &text);
     }
 
     // blit the contents of `panel` to the root console
@@ -859,15 +861,12 @@ fn render_all(root: &mut Root, con: &mut Offscreen, panel: &mut Offscreen, state
 
     // Clear stuff
     root.flush();
-    for object in &to_draw {
-        object.clear(con);
-    }
-    for obj in state.objects.iter().filter(|o| state.fov_map.is_in_fov(o.x, o.y)) {
-        obj.clear(con);
+    for &id in &to_draw {
+        clear_entity(con, state, id);
     }
-    state.player.clear(con);
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render_bar<S: Into<String>>(panel: &mut Offscreen,
               x: i32,
               y: i32,
@@ -878,7 +877,7 @@ fn render_bar<S: Into<String>>(panel: &mut Offscreen,
               bar_color: Color,
               back_color: Color)
 {
-    let bar_width = (value as f32 / maximum as f32 * total_width as f32) as i32;
+    let bar_width = std::cmp::min((value as f32 / maximum as f32 * total_width as f32) as i32, total_width);
 
     panel.set_default_background(back_color);
     panel.rect(x, y, total_width, 1, false, BackgroundFlag::Screen);
@@ -890,132 +889,37 @@ fn render_bar<S: Into<String>>(panel: &mut Offscreen,
 
     panel.set_default_foreground(colors::WHITE);
     panel.print_ex(x + total_width / 2, y, BackgroundFlag::None, TextAlignment::Center,
-                &format!("{}: {}/{}", name.into(), value, maximum));
+                format!("{}: {}/{}", name.into(), value, maximum));
 }
 
 
 
-fn handle_keys(root: &mut Root, con: &mut Offscreen, status: &mut Offscreen, state: &mut GameState, messages: &mut Messages) -> PlayerAction {
-    use tcod::input::Key;
-    use tcod::input::KeyCode::*;
+/// Loads the optional config file at `path` with `load`. Without the file it's the default,
+/// which is also used if the file can't be loaded, after saying so and what that means.
+fn load_or_default<T: Default, F: FnOnce(&'static str) -> io::Result<T>>(path: &'static str, load: F, fallback: &str) -> T {
+    if !Path::new(path).exists() {
+        return T::default();
+    }
+    load(path).unwrap_or_else(|e| {
+        println!("couldn't load {}, {}: {}", path, fallback, e);
+        T::default()
+    })
+}
+
+fn handle_keys(key: Key, bindings: &KeyBindings) -> PlayerAction {
     use PlayerAction::*;
-    use GameStage::*;
 
-    let key = root.wait_for_keypress(true);
     if key.pressed {
-        match (key, state.stage) {
-            (Key { code: Escape, .. }, _) => return Exit,  // exit game
-
-            // movement keys
-            (Key { code: Up, .. }, Playing) => {
-                let id = state.player.move_or_attack(0, -1, &mut state.map, &mut state.npcs[..], &mut state.objects[..], state.inventory.has_key, &mut state.stage, messages);
-                if let Some(id) = id {
-                    state.recent_enemy_id = Some(id);
-                }
-                return TookTurn;
-            },
-            (Key { code: Down, .. }, Playing) => {
-                let id = state.player.move_or_attack(0, 1, &mut state.map, &mut state.npcs[..], &mut state.objects[..], state.inventory.has_key, &mut state.stage, messages);
-                if let Some(id) = id {
-                    state.recent_enemy_id = Some(id);
-                }
-                return TookTurn;
-            },
-            (Key { code: Left, .. }, Playing) => {
-                let id = state.player.move_or_attack(-1, 0, &mut state.map, &mut state.npcs[..], &mut state.objects[..], state.inventory.has_key, &mut state.stage, messages);
-                if let Some(id) = id {
-                    state.recent_enemy_id = Some(id);
-                }
-                return TookTurn;
+        match bindings.command(&key_name(key)) {
+            Some(Command::Exit) => return Exit,  // exit game
+            Some(Command::Equip) => return ChooseEquipment,
+            Some(Command::Inventory) => return OpenInventory,
+            Some(Command::Look) => return Look,
+            Some(Command::History) => return ShowHistory,
+            Some(command) => if let Some(action) = command.action() {
+                return Act(action);
             },
-            (Key { code: Right, .. }, Playing) => {
-                let id = state.player.move_or_attack(1, 0, &mut state.map, &mut state.npcs[..], &mut state.objects[..], state.inventory.has_key, &mut state.stage, messages);
-                if let Some(id) = id {
-                    state.recent_enemy_id = Some(id);
-                }
-                return TookTurn;
-            },
-            (Key { code: Control, .. }, Playing) => {
-                if state.inventory.healing_potions > 0 {
-                    state.inventory.healing_potions -= 1;
-                    state.player.take_damage(-3, messages);
-                    print_message(messages, format!("Used a health potion! You have {} left.", state.inventory.healing_potions), colors::CHARTREUSE);
-
-                } else {
-                    print_message(messages, format!("No healing potions left!"), colors::RED);
-                }
-                return TookTurn;
-            },
-            (Key { code: Alt, .. }, Playing) => {
-                print_message(messages, format!("Waited a turn."), colors::GREY);
-                return TookTurn;
-            },
-            (Key { code: Enter, .. }, Title) => {
-                state.stage = Playing;
-                messages.clear();
-                return TookTurn;
-            },
-            (Key { code: Enter, .. }, GameOver) => {
-                println!("restarting");
-                let (initial_map, rooms, (px, py)) = generate_map(MAP_WIDTH-SCREEN_WIDTH-1, MAP_HEIGHT-SCREEN_HEIGHT-1);
-                let (px, py) = (px + SCREEN_WIDTH/2, py + SCREEN_HEIGHT/2);
-
-                let mut final_map = Map::new(MAP_WIDTH, MAP_HEIGHT, Tile::wall());
-
-                for y in 0..MAP_HEIGHT-SCREEN_HEIGHT-1 {
-                    for x in 0..MAP_WIDTH-SCREEN_WIDTH-1 {
-                        final_map.set(x+SCREEN_WIDTH/2, y+SCREEN_HEIGHT/2, initial_map.get(x, y));
-                    }
-                }
-
-                let npcs = generate_monsters(&rooms[1..], &mut final_map);
-                let objects = generate_objects(&rooms[..], &mut final_map);
-                let mut player = Entity::new(px, py, '@', colors::WHITE, "James", &mut final_map, true, true).unwrap();
-                player.fighter = Some(Fighter{
-                    max_hp: 30,
-                    hp: 30,
-                    defense: 2,
-                    power: 5,
-                    on_death: DeathCallback::Player,
-                });
-
-                state.player = player;
-                state.npcs = npcs;
-                state.objects = objects;
-                state.map = final_map;
-                state.camera_pos = (px, py);
-                state.fov_map = FovMap::new(MAP_WIDTH, MAP_HEIGHT);
-                state.prev_player_pos = (px, py);
-                state.stage = GameStage::Title;
-                state.recent_enemy_id = None;
-                state.inventory = Inventory { healing_potions: 0, has_key: false };
-
-                messages.clear();
-
-                print_message(messages, "New Game Started! Find the key in the Tomb of the Ancient King and bring it back here to unluck the box... or perish. Press Start to Begin!", colors::CYAN);
-
-                // compute initial fov
-                for y in 0..MAP_HEIGHT-1 {
-                    for x in 0..MAP_WIDTH {
-                        state.fov_map.set(x, y,
-                            !state.map.get(x, y).blocks_sight,
-                            !state.map.get(x, y).blocks_movement
-                        );
-                    }
-                }
-                compute_fov(state, true);
-                // Render initial state
-                root.clear();
-                con.clear();
-                status.clear();
-                render_all(root, con, status, &state, true, messages);
-                return TookTurn;
-            },
-            (Key { code: Enter, .. }, Won) => {
-                // RPI GPIO code here
-                return Exit;
-            },
-            _ => {},
+            None => {},
         }
     }
 
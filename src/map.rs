@@ -0,0 +1,177 @@
+use std;
+
+use bresenham::Bresenham;
+use rand::Rng;
+
+use game::GameRng;
+
+const ROOM_MAX_SIZE: i32 = 12;
+const ROOM_MIN_SIZE: i32 = 6;
+const MAX_ROOMS: i32 = 30;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Tile {
+    pub blocks_movement: bool,
+    pub blocks_sight: bool,
+    pub explored: bool,
+}
+
+impl Tile {
+    pub fn empty() -> Self {
+        Tile { blocks_movement: false, blocks_sight: false, explored: false }
+    }
+
+    pub fn wall() -> Self {
+        Tile { blocks_movement: true, blocks_sight: true, explored: false }
+    }
+
+    pub fn entity() -> Self {
+        Tile { blocks_movement: true, blocks_sight: false, explored: false }
+    }
+
+    pub fn is_wall(&self) -> bool {
+        self.blocks_movement && self.blocks_sight
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Map {
+    pub width: i32,
+    pub height: i32,
+    data: Vec<Vec<Tile>>,
+}
+
+impl Map {
+    pub fn new(width: i32, height: i32, default_tile: Tile) -> Self {
+        Map {
+            width,
+            height,
+            data: vec![vec![default_tile; height as usize]; width as usize]
+        }
+    }
+
+    /// Whether `(x, y)` is on the map at all.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.width && y < self.height
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Tile {
+        self.data[x as usize][y as usize]
+    }
+
+    pub fn get_mut(&mut self, x: i32, y: i32) -> &mut Tile {
+        &mut self.data[x as usize][y as usize]
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, tile: Tile) {
+        self.data[x as usize][y as usize] = tile
+    }
+
+    /// Whether a diagonal step of `(dx, dy)` from `(x, y)` would squeeze past the corner of
+    /// a wall.
+    pub fn cuts_corner(&self, x: i32, y: i32, dx: i32, dy: i32) -> bool {
+        dx != 0 && dy != 0 && (self.get(x + dx, y).is_wall() || self.get(x, y + dy).is_wall())
+    }
+
+    /// Whether nothing between `from` and `to` blocks sight. The end points themselves don't
+    /// count, so a monster standing in a doorway can still be seen.
+    pub fn line_of_sight(&self, from: (i32, i32), to: (i32, i32)) -> bool {
+        Bresenham::new((from.0 as isize, from.1 as isize), (to.0 as isize, to.1 as isize))
+            .skip(1)
+            .all(|(x, y)| !self.get(x as i32, y as i32).blocks_sight)
+    }
+
+    pub fn set_rect(&mut self, rect: Rect, tile: Tile, inclusive: bool) {
+        let initial_add = if inclusive { 0 } else { 1 };
+        let after_add = if inclusive { 1 } else { 0 };
+        for x in std::cmp::max(0, rect.x1 + initial_add)..std::cmp::min(self.width - 1, rect.x2 + after_add) {
+            for y in std::cmp::max(0, rect.y1 + initial_add)..std::cmp::min(self.height - 1, rect.y2 + after_add) {
+                self.set(x, y, tile);
+            }
+        }
+    }
+
+    pub fn set_tunnel(&mut self, start: (i32, i32), end: (i32, i32), radius: i32, tile: Tile) {
+        for (x,y) in Bresenham::new((start.0 as isize, start.1 as isize), (end.0 as isize, end.1 as isize)) {
+            self.set_rect(Rect::new(x as i32 - radius, y as i32 - radius, radius*2, radius*2), tile, true);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Rect {
+    pub x1: i32,
+    pub x2: i32,
+    pub y1: i32,
+    pub y2: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
+        Rect { x1: x, y1: y, x2: x + w, y2: y + h }
+    }
+
+    pub fn center(&self) -> (i32, i32) {
+        let center_x = (self.x1 + self.x2) / 2;
+        let center_y = (self.y1 + self.y2) / 2;
+        (center_x, center_y)
+    }
+
+    pub fn intersects_with(&self, other: &Rect) -> bool {
+        // returns true if this rectangle intersects with another one
+        (self.x1 <= other.x2) && (self.x2 >= other.x1) &&
+            (self.y1 <= other.y2) && (self.y2 >= other.y1)
+    }
+}
+
+pub fn generate_map(width: i32, height: i32, rng: &mut GameRng) -> (Map, Vec<Rect>, (i32, i32)) {
+    let mut map: Map;
+    let mut starting_position = (0, 0);
+    let mut rooms: Vec<Rect>;
+    loop {
+        map = Map::new(width, height, Tile::wall());
+        rooms = Vec::new();
+
+        for _ in 0..MAX_ROOMS {
+            let w = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+            let h = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+            let x = rng.gen_range(0, map.width - w);
+            let y = rng.gen_range(0, map.height - h);
+            let new_room = Rect::new(x, y, w, h);
+
+            let failed = rooms.iter().any(|other_room| new_room.intersects_with(other_room));
+            if !failed {
+                map.set_rect(new_room, Tile::empty(), false);
+                let (nx, ny) = new_room.center();
+                if rooms.is_empty() {
+                    starting_position = (nx, ny);
+                } else {
+                    let (px, py) = rooms[rooms.len() - 1].center();
+
+                    if rng.gen_range(0, 100) <= 10 {
+                        map.set_tunnel((px, py), (nx, ny), 1, Tile::empty());
+                    } else {
+                        map.set_rect(Rect{x1: std::cmp::min(px, nx), y1: py, x2: std::cmp::max(nx, px), y2: py}, Tile::empty(), true);
+                        map.set_rect(Rect{x1: nx, y1: std::cmp::min(py, ny), x2: nx, y2: std::cmp::max(ny, py)}, Tile::empty(), true);
+                    }
+                }
+                rooms.push(new_room);
+            }
+        }
+        let total = width * height;
+        let mut full = 0;
+        for y in 0..height-1 {
+            for x in 0..width-1 {
+                if map.get(x,y).is_wall() {
+                    full += 1;
+                }
+            }
+        }
+        let percent = full as f64 / total as f64;
+        if (0.4..=0.6).contains(&percent) {
+            break;
+        }
+    }
+
+    (map, rooms, starting_position)
+}
@@ -0,0 +1,263 @@
+use std::collections::VecDeque;
+use std::collections::vec_deque::Iter;
+use std::fmt;
+use std::iter::Skip;
+
+use tcod::colors::{self, Color};
+
+use data::Profile;
+use events::Subscriber;
+use game::{AttackKind, GameEvent, GameState, Stat};
+use item::with_article;
+
+/// How many messages the log keeps for the history screen before dropping the oldest.
+pub const MAX_MESSAGES: usize = 500;
+
+/// What a message is about, which decides its color and which filter of the history screen
+/// it shows up under.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Category {
+    Combat,
+    /// Combat going badly for the player.
+    Danger,
+    Loot,
+    System,
+    Story,
+    /// The gift being revealed.
+    Reveal,
+}
+
+/// The categories the history screen can be filtered by, in the order it cycles through them.
+pub const FILTERS: [Category; 4] = [Category::Combat, Category::Loot, Category::System, Category::Story];
+
+impl Category {
+    pub fn color(self, profile: &Profile) -> Color {
+        match self {
+            Category::Combat => colors::WHITE,
+            Category::Danger => colors::RED,
+            Category::Loot => colors::CHARTREUSE,
+            Category::System => colors::GREY,
+            Category::Story => profile.story_color,
+            Category::Reveal => profile.reveal_color,
+        }
+    }
+
+    /// The filter of the history screen this category shows up under.
+    pub fn filter(self) -> Category {
+        match self {
+            Category::Danger => Category::Combat,
+            Category::Reveal => Category::Story,
+            category => category,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self.filter() {
+            Category::Combat => "combat",
+            Category::Loot => "loot",
+            Category::Story => "story",
+            _ => "system",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Message {
+    pub text: String,
+    pub category: Category,
+    /// The turn it was last printed on.
+    pub turn: u32,
+    /// How many times in a row it was printed.
+    pub count: u32,
+}
+
+/// The text with how many times it was repeated, e.g. "Waited a turn. (x4)".
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.count > 1 {
+            write!(f, "{} (x{})", self.text, self.count)
+        } else {
+            write!(f, "{}", self.text)
+        }
+    }
+}
+
+/// The message log. The panel only shows what was printed since it was last cleared, the
+/// history screen shows all of it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Messages {
+    log: VecDeque<Message>,
+    /// Index into `log` of the first message the panel shows.
+    shown_from: usize,
+    /// The turn new messages are stamped with.
+    pub turn: u32,
+}
+
+impl Messages {
+    /// Every message still in the log, oldest first.
+    pub fn history<'a>(&'a self) -> Iter<'a, Message> {
+        self.log.iter()
+    }
+
+    /// The messages printed since the panel was last cleared, oldest first.
+    pub fn recent<'a>(&'a self) -> Skip<Iter<'a, Message>> {
+        self.log.iter().skip(self.shown_from)
+    }
+
+    /// Clears the panel. The messages are kept in the history.
+    pub fn clear(&mut self) {
+        self.shown_from = self.log.len();
+    }
+}
+
+/// Turns what happened into lines in the log.
+impl Subscriber for Messages {
+    fn notify(&mut self, event: &GameEvent, state: &GameState) {
+        match *event {
+            GameEvent::Message(ref text, category) => print_message(self, text.clone(), category),
+            GameEvent::ClearMessages => self.clear(),
+            GameEvent::Attacked { ref attacker, ref target, damage, by_player, ref kind } => {
+                let category = if by_player { Category::Combat } else { Category::Danger };
+                match *kind {
+                    AttackKind::Melee if damage > 0 => {
+                        print_message(self, format!("{} attacks {} for {} hp!", attacker, target, damage), category);
+                    },
+                    AttackKind::Melee => {
+                        print_message(self, format!("{} attacks {} but it has no effect... ", attacker, target), Category::Combat);
+                    },
+                    AttackKind::Lightning => {
+                        print_message(self, format!("A lightning bolt strikes the {} with a loud thunder for {} hp!", target, damage), category);
+                    },
+                    AttackKind::Fireball if by_player => {
+                        print_message(self, format!("The {} gets burned for {} hp.", target, damage), category);
+                    },
+                    AttackKind::Fireball => print_message(self, format!("You get burned for {} hp.", damage), category),
+                    AttackKind::Thrown(ref item) if damage > 0 => {
+                        print_message(self, format!("The {} hits {} for {} hp!", item, target, damage), category);
+                    },
+                    AttackKind::Thrown(ref item) => print_message(self, format!("The {} bounces off {}.", item, target), category),
+                }
+            },
+            GameEvent::Died { is_player: true, .. } => print_message(self, "You died!", Category::Danger),
+            GameEvent::Died { ref name, .. } => print_message(self, format!("{} died!", name), Category::Combat),
+            GameEvent::PickedUp(ref item) => print_message(self, format!("You picked up {}!", with_article(item)), Category::Loot),
+            GameEvent::PickedUpGold(amount) => print_message(self, format!("You picked up {} gold!", amount), Category::Loot),
+            GameEvent::PackFull(ref item) => {
+                print_message(self, format!("Your pack is full, you leave {} where it is.", with_article(item)), Category::Loot);
+            },
+            GameEvent::LootDropped { ref monster, ref item } => {
+                print_message(self, format!("The {} drops {}.", monster, with_article(item)), Category::Loot);
+            },
+            GameEvent::GoldDropped { ref monster, amount } => {
+                print_message(self, format!("The {} drops {} gold.", monster, amount), Category::Loot);
+            },
+            GameEvent::ItemUsed { ref item, left } => {
+                print_message(self, format!("Used {}! You have {} left.", with_article(item), left), Category::Loot);
+            },
+            GameEvent::Thrown(ref item) => print_message(self, format!("You throw the {}.", item), Category::Combat),
+            GameEvent::Shattered(ref item) => print_message(self, format!("The {} shatters.", item), Category::Combat),
+            GameEvent::Dropped(ref item) => print_message(self, format!("You drop the {}.", item), Category::Loot),
+            GameEvent::Equipped(ref item) => print_message(self, format!("You equip the {}.", item), Category::Loot),
+            GameEvent::Unequipped(ref item) => print_message(self, format!("You take off the {}.", item), Category::Loot),
+            GameEvent::FireballExploded => {
+                print_message(self, "The fireball explodes, burning everything around it!", Category::Combat);
+            },
+            GameEvent::Confused(ref monster) => {
+                print_message(self, format!("The eyes of the {} look vacant, as it starts to stumble around!", monster), Category::Combat);
+            },
+            GameEvent::ConfusionEnded(ref monster) => {
+                print_message(self, format!("The {} is no longer confused!", monster), Category::Danger);
+            },
+            GameEvent::StatRaised(Stat::MaxHp) => print_message(self, "You feel more resilient!", Category::Loot),
+            GameEvent::StatRaised(Stat::Power) => print_message(self, "You feel stronger!", Category::Loot),
+            GameEvent::StatRaised(Stat::Defense) => print_message(self, "Your skin hardens!", Category::Loot),
+            GameEvent::ChestLocked => print_message(self, "You need a key to open this chest, not a sword.", Category::System),
+            GameEvent::ChestOpened => {
+                print_message(self, state.data.profile.win_text.clone(), Category::Story);
+                print_message(self, state.data.profile.reveal_text.clone(), Category::Reveal);
+            },
+            GameEvent::LeveledUp(level) => print_message(self, format!("You reached level {}!", level), Category::System),
+            GameEvent::StageChanged(_) | GameEvent::FovRecomputed | GameEvent::NewGame
+                | GameEvent::LevelChanged(_) | GameEvent::RevealGift => {},
+        }
+    }
+}
+
+/// Adds a message to the log. The same message printed again while the last one is still on
+/// the panel just counts up the last one instead.
+pub fn print_message<T: Into<String>>(messages: &mut Messages, message: T, category: Category) {
+    let text = message.into();
+    let turn = messages.turn;
+    if messages.log.len() > messages.shown_from {
+        if let Some(last) = messages.log.back_mut() {
+            if last.text == text && last.category == category {
+                last.count += 1;
+                last.turn = turn;
+                return;
+            }
+        }
+    }
+
+    if messages.log.len() == MAX_MESSAGES {
+        messages.log.pop_front();
+        messages.shown_from = messages.shown_from.saturating_sub(1);
+    }
+
+    messages.log.push_back(Message { text, category, turn, count: 1 });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shown(messages: &Messages) -> Vec<String> {
+        messages.recent().map(|m| m.to_string()).collect()
+    }
+
+    #[test]
+    fn repeats_are_collapsed_with_a_count() {
+        let mut messages = Messages::default();
+        print_message(&mut messages, "Waited a turn.", Category::System);
+        messages.turn = 3;
+        print_message(&mut messages, "Waited a turn.", Category::System);
+        print_message(&mut messages, "Waited a turn.", Category::System);
+
+        assert_eq!(shown(&messages), vec!["Waited a turn. (x3)"]);
+        assert_eq!(messages.history().next().map(|m| m.turn), Some(3));
+    }
+
+    #[test]
+    fn a_different_category_breaks_the_run() {
+        let mut messages = Messages::default();
+        print_message(&mut messages, "Boo!", Category::System);
+        print_message(&mut messages, "Boo!", Category::Danger);
+        print_message(&mut messages, "Boo!", Category::Danger);
+
+        assert_eq!(shown(&messages), vec!["Boo!", "Boo! (x2)"]);
+    }
+
+    #[test]
+    fn repeats_after_a_clear_start_over() {
+        let mut messages = Messages::default();
+        print_message(&mut messages, "Waited a turn.", Category::System);
+        messages.clear();
+        print_message(&mut messages, "Waited a turn.", Category::System);
+
+        assert_eq!(shown(&messages), vec!["Waited a turn."]);
+        assert_eq!(messages.history().count(), 2);
+    }
+
+    #[test]
+    fn the_oldest_messages_are_dropped_past_the_limit() {
+        let mut messages = Messages::default();
+        for i in 0..MAX_MESSAGES + 10 {
+            print_message(&mut messages, format!("Message {}", i), Category::System);
+        }
+        messages.clear();
+        print_message(&mut messages, "Last one.", Category::System);
+
+        assert_eq!(messages.history().count(), MAX_MESSAGES);
+        assert_eq!(messages.history().next().map(|m| m.text.as_str()), Some("Message 11"));
+        assert_eq!(shown(&messages), vec!["Last one."]);
+    }
+}
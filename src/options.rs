@@ -0,0 +1,22 @@
+use std::io;
+use std::path::Path;
+
+use data::read_toml;
+
+pub const OPTIONS_FILE: &str = "options.toml";
+
+/// Rules a player can change between runs. They're part of the game state, so a continued or
+/// replayed game plays by the rules it was started with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameOptions {
+    /// Lets the player and monsters move and attack diagonally, as long as they don't cut
+    /// across the corner of a wall.
+    pub diagonal_movement: bool,
+}
+
+impl GameOptions {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        read_toml(path)
+    }
+}
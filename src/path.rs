@@ -0,0 +1,249 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use map::Map;
+
+/// Extra cost of walking through a tile another monster is standing on. High enough that
+/// monsters go around each other when there's room, low enough that a crowded corridor
+/// doesn't stop them from queueing up behind each other.
+const SOFT_OBSTACLE_COST: i32 = 5;
+
+const DIRECTIONS: [(i32, i32); 8] = [(0, -1), (0, 1), (-1, 0), (1, 0), (-1, -1), (1, -1), (-1, 1), (1, 1)];
+
+/// A path an entity is following. It's kept between turns, so it only has to be searched for
+/// again when the target gets away from its end or something blocks the way.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Path {
+    pub target: (i32, i32),
+    /// The tiles still to walk, the last one being the next step.
+    steps: Vec<(i32, i32)>,
+    diagonal: bool,
+}
+
+impl Path {
+    /// The next tile to step onto, if the target hasn't been reached yet.
+    pub fn next(&self) -> Option<(i32, i32)> {
+        self.steps.last().cloned()
+    }
+
+    /// Marks the next step as taken.
+    pub fn advance(&mut self) {
+        self.steps.pop();
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Points the path at `target`. That only works without a new search if the target is
+    /// where it was or has moved one tile on from the end of the path; returns whether it was.
+    pub fn retarget(&mut self, target: (i32, i32)) -> bool {
+        if target == self.target {
+            return true;
+        }
+        let (dx, dy) = ((target.0 - self.target.0).abs(), (target.1 - self.target.1).abs());
+        let one_step = if self.diagonal { dx.max(dy) == 1 } else { dx + dy == 1 };
+        if one_step {
+            self.steps.insert(0, target);
+            self.target = target;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Node {
+    estimate: i32,
+    cost: i32,
+    pos: (i32, i32),
+}
+
+// BinaryHeap is a max-heap, so the order is reversed to pop the cheapest estimate first
+impl Ord for Node {
+    fn cmp(&self, other: &Node) -> Ordering {
+        other.estimate.cmp(&self.estimate).then_with(|| self.cost.cmp(&other.cost))
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Node) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Finds the cheapest path from `from` to `to` with A*. Tiles that block movement can't be
+/// walked through, except for `to` itself and the `soft_obstacles` (usually other monsters),
+/// which only cost more. With `diagonal` set the path can also take diagonal steps that don't
+/// cut across a wall's corner. Returns `None` if there's no way through.
+pub fn find_path(map: &Map, from: (i32, i32), to: (i32, i32), soft_obstacles: &[(i32, i32)], diagonal: bool) -> Option<Path> {
+    let index = |(x, y): (i32, i32)| (y * map.width + x) as usize;
+    let heuristic = |(x, y): (i32, i32)| {
+        let (dx, dy) = ((x - to.0).abs(), (y - to.1).abs());
+        if diagonal { dx.max(dy) } else { dx + dy }
+    };
+    let directions = if diagonal { &DIRECTIONS[..] } else { &DIRECTIONS[..4] };
+
+    let size = (map.width * map.height) as usize;
+    let mut best_cost = vec![i32::MAX; size];
+    let mut came_from: Vec<Option<(i32, i32)>> = vec![None; size];
+    let mut open = BinaryHeap::new();
+
+    best_cost[index(from)] = 0;
+    open.push(Node { estimate: heuristic(from), cost: 0, pos: from });
+
+    while let Some(Node { cost, pos, .. }) = open.pop() {
+        if pos == to {
+            let mut steps = vec![];
+            let mut current = to;
+            while current != from {
+                steps.push(current);
+                current = came_from[index(current)].expect("every visited tile has a parent");
+            }
+            return Some(Path { target: to, steps, diagonal });
+        }
+        if cost > best_cost[index(pos)] {
+            // a cheaper way here was already found
+            continue;
+        }
+
+        for &(dx, dy) in directions {
+            let next = (pos.0 + dx, pos.1 + dy);
+            if next.0 < 0 || next.1 < 0 || next.0 >= map.width || next.1 >= map.height {
+                continue;
+            }
+            if map.cuts_corner(pos.0, pos.1, dx, dy) {
+                continue;
+            }
+            let step_cost = if next == to {
+                1
+            } else if soft_obstacles.contains(&next) {
+                1 + SOFT_OBSTACLE_COST
+            } else if map.get(next.0, next.1).blocks_movement {
+                continue;
+            } else {
+                1
+            };
+            let next_cost = cost + step_cost;
+            if next_cost < best_cost[index(next)] {
+                best_cost[index(next)] = next_cost;
+                came_from[index(next)] = Some(pos);
+                open.push(Node { estimate: next_cost + heuristic(next), cost: next_cost, pos: next });
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use map::Tile;
+
+    /// A map drawn with `#` for walls, `m` for a monster and anything else for floor.
+    fn map_from(rows: &[&str]) -> Map {
+        let mut map = Map::new(rows[0].len() as i32, rows.len() as i32, Tile::empty());
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                match c {
+                    '#' => map.set(x as i32, y as i32, Tile::wall()),
+                    'm' => map.set(x as i32, y as i32, Tile::entity()),
+                    _ => {},
+                }
+            }
+        }
+        map
+    }
+
+    /// Every tile the path leads over, in the order they're walked.
+    fn walk(mut path: Path) -> Vec<(i32, i32)> {
+        let mut tiles = vec![];
+        while let Some(tile) = path.next() {
+            tiles.push(tile);
+            path.advance();
+        }
+        tiles
+    }
+
+    #[test]
+    fn diagonal_paths_go_around_wall_corners() {
+        let map = map_from(&[
+            "#####",
+            "#..##",
+            "##..#",
+            "#####",
+        ]);
+        let path = find_path(&map, (1, 1), (3, 2), &[], true).unwrap();
+        assert_eq!(walk(path), vec![(2, 1), (2, 2), (3, 2)]);
+    }
+
+    #[test]
+    fn monsters_are_walked_around_when_there_is_room() {
+        let map = map_from(&[
+            "#######",
+            "#..m..#",
+            "#.###.#",
+            "#.....#",
+            "#######",
+        ]);
+        let path = find_path(&map, (1, 1), (5, 1), &[(3, 1)], false).unwrap();
+        let tiles = walk(path);
+        assert_eq!(tiles.len(), 8);
+        assert!(!tiles.contains(&(3, 1)));
+    }
+
+    #[test]
+    fn monsters_are_queued_behind_when_there_is_no_room() {
+        let map = map_from(&[
+            "#######",
+            "#..m..#",
+            "#######",
+        ]);
+        let path = find_path(&map, (1, 1), (5, 1), &[(3, 1)], false).unwrap();
+        assert_eq!(walk(path), vec![(2, 1), (3, 1), (4, 1), (5, 1)]);
+        // without being told it's a monster, it's in the way like a wall
+        assert_eq!(find_path(&map, (1, 1), (5, 1), &[], false), None);
+    }
+
+    #[test]
+    fn walled_off_targets_are_unreachable() {
+        let map = map_from(&[
+            "#######",
+            "#..#..#",
+            "#######",
+        ]);
+        assert_eq!(find_path(&map, (1, 1), (5, 1), &[], true), None);
+    }
+
+    #[test]
+    fn retargeting_reuses_the_path_only_for_one_more_step() {
+        let map = map_from(&[
+            "#######",
+            "#.....#",
+            "#.....#",
+            "#######",
+        ]);
+        let mut path = find_path(&map, (1, 1), (4, 1), &[], false).unwrap();
+        let before = path.clone();
+        assert!(path.retarget((4, 1)));
+        assert_eq!(path, before);
+
+        assert!(path.retarget((5, 1)));
+        assert_eq!(path.target, (5, 1));
+        assert_eq!(walk(path.clone()), vec![(2, 1), (3, 1), (4, 1), (5, 1)]);
+
+        // a diagonal step away needs a new search without diagonal movement, as does a jump
+        assert!(!path.retarget((4, 2)));
+        assert!(!path.retarget((1, 2)));
+        assert_eq!(path.target, (5, 1));
+
+        let mut diagonal = find_path(&map, (1, 1), (4, 1), &[], true).unwrap();
+        assert!(diagonal.retarget((5, 2)));
+        assert_eq!(walk(diagonal).last(), Some(&(5, 2)));
+    }
+}
@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use serde_json::{self, Value};
+
+use game::{Action, GameState};
+use options::GameOptions;
+
+/// Bump this whenever `Action` or the replay layout changes.
+pub const REPLAY_VERSION: u64 = 1;
+
+pub const REPLAY_FILE: &str = "replay.json";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedInput {
+    /// The key that was pressed, only kept to make replays readable.
+    pub key: String,
+    pub action: Action,
+}
+
+/// Everything needed to play a run back: the seed and options it was started with and every input
+/// that was fed to `GameState::step`, in order.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u64,
+    pub seed: u64,
+    pub options: GameOptions,
+    pub inputs: Vec<RecordedInput>,
+    /// `fingerprint` of the state the recording ended in.
+    pub final_fingerprint: Option<u64>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, options: GameOptions) -> Self {
+        Replay {
+            version: REPLAY_VERSION,
+            seed,
+            options,
+            inputs: Vec::new(),
+            final_fingerprint: None,
+        }
+    }
+
+    pub fn record<S: Into<String>>(&mut self, key: S, action: Action) {
+        self.inputs.push(RecordedInput { key: key.into(), action });
+    }
+
+    pub fn finish(&mut self, state: &GameState) {
+        self.final_fingerprint = Some(fingerprint(state));
+    }
+
+    /// Whether `state` is the same state the recording ended in.
+    pub fn matches(&self, state: &GameState) -> Option<bool> {
+        self.final_fingerprint.map(|expected| expected == fingerprint(state))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Replay> {
+        let reader = BufReader::new(File::open(path)?);
+        let value: Value = serde_json::from_reader(reader)?;
+
+        let version = value.get("version").and_then(Value::as_u64);
+        if version != Some(REPLAY_VERSION) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("unsupported replay version {:?}, expected {}", version, REPLAY_VERSION)));
+        }
+
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+/// A 64 bit FNV-1a hash of the serialized state. Unlike `DefaultHasher` it's stable between
+/// builds, so replays recorded by one binary can be checked by another.
+pub fn fingerprint(state: &GameState) -> u64 {
+    let bytes = serde_json::to_vec(state).expect("game state is always serializable");
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
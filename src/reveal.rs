@@ -0,0 +1,234 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use data::read_toml;
+
+pub const REVEAL_FILE: &str = "reveal.toml";
+
+/// Something that can be switched on to open the gift, like a solenoid lock wired to a few
+/// GPIO lines. It's handed to a worker thread to end a pulse, so it has to be `Send`.
+pub trait RevealDevice: Send {
+    /// Drives every line high (`true`) or low.
+    fn set(&mut self, high: bool) -> io::Result<()>;
+}
+
+/// How `reveal.toml` describes the device, e.g. `backend = "gpio"`, `chip = "/dev/gpiochip0"`,
+/// `lines = [17]`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+enum DeviceConfig {
+    Nothing,
+    /// GPIO lines driven through the Linux character-device interface.
+    Gpio {
+        chip: String,
+        lines: Vec<u32>,
+        #[serde(default)]
+        active_low: bool,
+    },
+    /// Logs what would have been done to a file instead.
+    Mock { path: String },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct RevealConfig {
+    #[serde(flatten)]
+    device: DeviceConfig,
+    /// How long to hold the lines high before letting go. Left at 0 they stay high.
+    #[serde(default)]
+    pulse_ms: u64,
+}
+
+/// Opens the gift once the player has won.
+#[derive(Default)]
+pub struct Reveal {
+    device: Option<Box<dyn RevealDevice>>,
+    pulse: Option<Duration>,
+}
+
+impl Reveal {
+    pub fn new(device: Box<dyn RevealDevice>, pulse: Option<Duration>) -> Self {
+        Reveal { device: Some(device), pulse }
+    }
+
+    /// Reads the config at `path` and opens the device it describes.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let config: RevealConfig = read_toml(path)?;
+
+        let pulse = if config.pulse_ms > 0 { Some(Duration::from_millis(config.pulse_ms)) } else { None };
+        let device: Box<dyn RevealDevice> = match config.device {
+            DeviceConfig::Nothing => return Ok(Reveal::default()),
+            DeviceConfig::Gpio { chip, lines, active_low } => Box::new(GpioDevice::open(&chip, &lines, active_low)?),
+            DeviceConfig::Mock { path } => Box::new(MockDevice::new(path)),
+        };
+        Ok(Reveal::new(device, pulse))
+    }
+
+    /// Switches the lines on. With a pulse they're switched off again on a worker thread, so
+    /// the game keeps running in the meantime, and the pulse is handed back to be checked on.
+    /// The device goes with it, since the gift only opens once.
+    pub fn reveal(&mut self) -> io::Result<Option<Pulse>> {
+        match self.device {
+            Some(ref mut device) => device.set(true)?,
+            None => return Ok(None),
+        }
+        let pulse = match self.pulse {
+            Some(pulse) => pulse,
+            None => return Ok(None),
+        };
+        let mut device = self.device.take().expect("checked above");
+        let (sender, done) = mpsc::channel();
+        thread::spawn(move || {
+            thread::sleep(pulse);
+            // nobody's listening anymore if the game has already been closed
+            let _ = sender.send(device.set(false));
+        });
+        Ok(Some(Pulse { done }))
+    }
+}
+
+/// A pulse from `Reveal::reveal` that might still be holding the lines on.
+pub struct Pulse {
+    done: Receiver<io::Result<()>>,
+}
+
+impl Pulse {
+    /// `None` while the lines are still on, otherwise whether switching them off worked.
+    pub fn finished(&self) -> Option<io::Result<()>> {
+        match self.done.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Ok(())),
+        }
+    }
+
+    /// Blocks until the lines are off again.
+    pub fn wait(self) -> io::Result<()> {
+        self.done.recv().unwrap_or(Ok(()))
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub struct GpioDevice {
+    handle: ::gpio_cdev::MultiLineHandle,
+}
+
+#[cfg(target_os = "linux")]
+impl GpioDevice {
+    /// Requests `lines` on `chip` as outputs, starting low.
+    pub fn open(chip: &str, lines: &[u32], active_low: bool) -> io::Result<Self> {
+        use gpio_cdev::{Chip, LineRequestFlags};
+
+        let mut flags = LineRequestFlags::OUTPUT;
+        if active_low {
+            flags |= LineRequestFlags::ACTIVE_LOW;
+        }
+        let handle = Chip::new(chip)
+            .and_then(|mut chip| chip.get_lines(lines))
+            .and_then(|lines| lines.request(flags, &vec![0; lines.len()], "giftrogue"))
+            .map_err(gpio_error)?;
+        Ok(GpioDevice { handle })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl RevealDevice for GpioDevice {
+    fn set(&mut self, high: bool) -> io::Result<()> {
+        let values = vec![high as u8; self.handle.num_lines()];
+        self.handle.set_values(&values).map_err(gpio_error)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn gpio_error(e: ::gpio_cdev::Error) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub struct GpioDevice;
+
+#[cfg(not(target_os = "linux"))]
+impl GpioDevice {
+    pub fn open(_chip: &str, _lines: &[u32], _active_low: bool) -> io::Result<Self> {
+        Err(io::Error::other("GPIO is only supported on Linux"))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl RevealDevice for GpioDevice {
+    fn set(&mut self, _high: bool) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Appends a line to a file every time the lines would have been switched, so the win flow
+/// can be tried out without the box.
+pub struct MockDevice {
+    path: PathBuf,
+}
+
+impl MockDevice {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        MockDevice { path: path.into() }
+    }
+}
+
+impl RevealDevice for MockDevice {
+    fn set(&mut self, high: bool) -> io::Result<()> {
+        let mut log = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(log, "lines {}", if high { "high" } else { "low" })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Read;
+
+    use super::*;
+
+    /// A reveal set up from a config with a mock device, and the file the device logs to.
+    fn mock_reveal(name: &str, pulse_ms: u64) -> (Reveal, PathBuf) {
+        let dir = env::temp_dir();
+        let log = dir.join(format!("giftrogue-{}-{}.log", name, ::std::process::id()));
+        let config = dir.join(format!("giftrogue-{}-{}.toml", name, ::std::process::id()));
+        let _ = fs::remove_file(&log);
+        File::create(&config).unwrap()
+            .write_all(format!("backend = \"mock\"\npath = {:?}\npulse_ms = {}\n", log.display().to_string(), pulse_ms).as_bytes())
+            .unwrap();
+        let reveal = Reveal::load(&config);
+        let _ = fs::remove_file(&config);
+        (reveal.unwrap(), log)
+    }
+
+    fn read_log(log: &Path) -> String {
+        let mut contents = String::new();
+        File::open(log).unwrap().read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn a_pulse_lets_go_of_the_lines_in_the_background() {
+        let (mut reveal, log) = mock_reveal("pulse", 50);
+        let pulse = reveal.reveal().unwrap().expect("a pulse was configured");
+        assert_eq!(read_log(&log), "lines high\n");
+        pulse.wait().unwrap();
+        assert_eq!(read_log(&log), "lines high\nlines low\n");
+        // the gift is open, there's nothing left to switch
+        assert!(reveal.reveal().unwrap().is_none());
+        assert_eq!(read_log(&log), "lines high\nlines low\n");
+        let _ = fs::remove_file(&log);
+    }
+
+    #[test]
+    fn without_a_pulse_the_lines_stay_on() {
+        let (mut reveal, log) = mock_reveal("no-pulse", 0);
+        assert!(reveal.reveal().unwrap().is_none());
+        assert_eq!(read_log(&log), "lines high\n");
+        let _ = fs::remove_file(&log);
+    }
+}
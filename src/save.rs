@@ -0,0 +1,78 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+use std::rc::Rc;
+
+use rand::Rng;
+use serde_json::{self, Value};
+
+use data::GameData;
+use game::{new_rng, GameState};
+use messages::Messages;
+
+/// Bump this whenever the layout of `GameState` changes in a way old saves can't be read as.
+pub const SAVE_VERSION: u64 = 1;
+
+pub const SAVE_FILE: &str = "savegame.json";
+
+#[derive(Serialize)]
+struct SaveFileRef<'a> {
+    version: u64,
+    rng_seed: u64,
+    state: &'a GameState,
+    messages: &'a Messages,
+}
+
+#[derive(Deserialize)]
+struct SaveFile {
+    rng_seed: u64,
+    state: GameState,
+    messages: Messages,
+}
+
+pub fn save_exists<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref().exists()
+}
+
+/// Writes the game to `path`. The RNG is reseeded from itself first so that a resumed game
+/// continues exactly like the one that was saved.
+pub fn save_game<P: AsRef<Path>>(path: P, state: &mut GameState, messages: &Messages) -> io::Result<()> {
+    let rng_seed = state.rng.gen();
+    state.rng = new_rng(rng_seed);
+
+    let save = SaveFileRef {
+        version: SAVE_VERSION,
+        rng_seed,
+        state,
+        messages,
+    };
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(writer, &save)?;
+    Ok(())
+}
+
+pub fn load_game<P: AsRef<Path>>(path: P, data: Rc<GameData>) -> io::Result<(GameState, Messages)> {
+    let reader = BufReader::new(File::open(path)?);
+    let value: Value = serde_json::from_reader(reader)?;
+
+    let version = value.get("version").and_then(Value::as_u64);
+    if version != Some(SAVE_VERSION) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("unsupported save version {:?}, expected {}", version, SAVE_VERSION)));
+    }
+
+    let save: SaveFile = serde_json::from_value(value)?;
+    let mut state = save.state;
+    state.rng = new_rng(save.rng_seed);
+    state.data = data;
+    state.rebuild_fov();
+    Ok((state, save.messages))
+}
+
+/// Removes the save at `path`, if there is one.
+pub fn delete_save<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
@@ -0,0 +1,36 @@
+use events::Subscriber;
+use game::{GameEvent, GameState};
+
+/// What the player got up to during a run, for the summary shown once it's over. Counted from
+/// when the run was started or continued.
+#[derive(Debug, Default)]
+pub struct Statistics {
+    pub kills: u32,
+    pub damage_dealt: i32,
+    pub damage_taken: i32,
+    pub items_picked_up: u32,
+    pub gold_picked_up: u32,
+    pub items_used: u32,
+}
+
+impl Statistics {
+    pub fn summary(&self) -> String {
+        format!("You killed {} monsters, dealt {} damage and took {}, picked up {} items and {} gold and used {} items.",
+                self.kills, self.damage_dealt, self.damage_taken, self.items_picked_up, self.gold_picked_up, self.items_used)
+    }
+}
+
+impl Subscriber for Statistics {
+    fn notify(&mut self, event: &GameEvent, _state: &GameState) {
+        match *event {
+            GameEvent::NewGame => *self = Statistics::default(),
+            GameEvent::Attacked { damage, by_player: true, .. } => self.damage_dealt += damage,
+            GameEvent::Attacked { damage, by_player: false, .. } => self.damage_taken += damage,
+            GameEvent::Died { is_player: false, .. } => self.kills += 1,
+            GameEvent::PickedUp(_) => self.items_picked_up += 1,
+            GameEvent::PickedUpGold(amount) => self.gold_picked_up += amount,
+            GameEvent::ItemUsed { .. } => self.items_used += 1,
+            _ => {},
+        }
+    }
+}
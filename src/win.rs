@@ -0,0 +1,95 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use data::read_toml;
+use messages::{print_message, Category, Messages};
+
+pub const WIN_ACTION_FILE: &str = "win.toml";
+
+/// What happens outside the game once the chest is opened, e.g. `action = "run_command"` with
+/// `command = "/home/pi/clean_startup.sh"` on the original cabinet.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum WinAction {
+    #[default]
+    Nothing,
+    /// Runs `command` with `sh -c`. The game carries on while it runs.
+    RunCommand { command: String },
+    WriteFile { path: String, contents: String },
+    ShowMessage { text: String },
+}
+
+impl WinAction {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        read_toml(path)
+    }
+
+    /// Carries the action out. Anything that goes wrong ends up in the message log, since
+    /// the game has already been won by then. A command that started is handed back, to be
+    /// checked on until it's done.
+    pub fn run(&self, messages: &mut Messages) -> Option<RunningCommand> {
+        use self::WinAction::*;
+        let result = match *self {
+            Nothing => Ok(()),
+            RunCommand { ref command } => match RunningCommand::start(command) {
+                Ok(running) => return Some(running),
+                Err(e) => Err(e),
+            },
+            WriteFile { ref path, ref contents } => File::create(path).and_then(|mut f| f.write_all(contents.as_bytes())),
+            ShowMessage { ref text } => {
+                print_message(messages, text.clone(), Category::Reveal);
+                Ok(())
+            },
+        };
+        if let Err(e) = result {
+            report(messages, &e);
+        }
+        None
+    }
+}
+
+/// A command from `WinAction::RunCommand` that might still be running. It's waited on in the
+/// background so the game stays responsive.
+pub struct RunningCommand {
+    done: Receiver<io::Result<()>>,
+}
+
+impl RunningCommand {
+    fn start(command: &str) -> io::Result<Self> {
+        let mut child = Command::new("sh").arg("-c").arg(command).spawn()?;
+        let command = command.to_string();
+        let (sender, done) = mpsc::channel();
+        thread::spawn(move || {
+            let result = child.wait().and_then(|status| if status.success() {
+                Ok(())
+            } else {
+                Err(io::Error::other(format!("`{}` failed with {}", command, status)))
+            });
+            // nobody's listening anymore if the game has already been closed
+            let _ = sender.send(result);
+        });
+        Ok(RunningCommand { done })
+    }
+
+    /// Whether the command has finished. If it failed, that's logged like any other problem
+    /// with the win action.
+    pub fn finished(&self, messages: &mut Messages) -> bool {
+        match self.done.try_recv() {
+            Ok(Ok(())) => true,
+            Ok(Err(e)) => {
+                report(messages, &e);
+                true
+            },
+            Err(TryRecvError::Empty) => false,
+            Err(TryRecvError::Disconnected) => true,
+        }
+    }
+}
+
+fn report(messages: &mut Messages, e: &io::Error) {
+    print_message(messages, format!("Something went wrong with the chest: {}", e), Category::Danger);
+}
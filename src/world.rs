@@ -0,0 +1,286 @@
+use ai::Ai;
+use data::LootTable;
+use entity::{Blocks, Chest, Fighter, Glyph, Position, Stairs};
+use item::Item;
+use path::Path;
+
+/// A handle to something in the `World`. Slots are reused once the entity in them is gone, so
+/// a handle also carries the generation of its slot: one that outlived its entity never finds
+/// whatever took its place.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EntityId {
+    index: usize,
+    generation: u32,
+}
+
+/// Every component of one kind, kept by the slot of the entity it belongs to.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Storage<T> {
+    slots: Vec<Option<(u32, T)>>,
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Self {
+        Storage { slots: Vec::new() }
+    }
+}
+
+impl<T> Storage<T> {
+    pub fn get(&self, id: EntityId) -> Option<&T> {
+        match self.slots.get(id.index) {
+            Some(&Some((generation, ref component))) if generation == id.generation => Some(component),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut T> {
+        match self.slots.get_mut(id.index) {
+            Some(&mut Some((generation, ref mut component))) if generation == id.generation => Some(component),
+            _ => None,
+        }
+    }
+
+    pub fn has(&self, id: EntityId) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// Gives `id` this component, replacing the one it had.
+    pub fn insert(&mut self, id: EntityId, component: T) {
+        while self.slots.len() <= id.index {
+            self.slots.push(None);
+        }
+        self.slots[id.index] = Some((id.generation, component));
+    }
+
+    pub fn remove(&mut self, id: EntityId) -> Option<T> {
+        if !self.has(id) {
+            return None;
+        }
+        self.slots[id.index].take().map(|(_, component)| component)
+    }
+
+    /// Every entity that has this component, along with it, oldest slot first.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (EntityId, &'a T)> + 'a {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| match *slot {
+            Some((generation, ref component)) => Some((EntityId { index, generation }, component)),
+            None => None,
+        })
+    }
+
+    fn copy(&mut self, from: EntityId, to: EntityId) where T: Clone {
+        if let Some(component) = self.get(from).cloned() {
+            self.insert(to, component);
+        }
+    }
+}
+
+/// Every entity in the game, on any level or in the player's pack. An entity is nothing but its
+/// id; what it is comes from the components stored for it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct World {
+    /// The generation of each slot. It's bumped when its entity goes away, so old ids stop
+    /// matching straight away.
+    generations: Vec<u32>,
+    /// Slots that are free to be reused.
+    free: Vec<usize>,
+    /// Where entities are on the dungeon floor. Items in the pack have none. Only changed
+    /// through `set_position` and `remove_position`, which keep `by_depth` up to date.
+    positions: Storage<Position>,
+    /// The entities on each level, by depth, oldest slot first. Lets lookups on one level
+    /// skip over everything left behind on the others.
+    by_depth: Vec<Vec<EntityId>>,
+    pub glyphs: Storage<Glyph>,
+    pub names: Storage<String>,
+    pub blocks: Storage<Blocks>,
+    pub fighters: Storage<Fighter>,
+    pub ais: Storage<Ai>,
+    pub items: Storage<Item>,
+    pub chests: Storage<Chest>,
+    pub stairs: Storage<Stairs>,
+    /// Where a monster was last heading, so it doesn't have to search for a path every turn.
+    pub paths: Storage<Path>,
+    /// What a monster drops when it dies. Taken once it has been dropped.
+    pub loot: Storage<LootTable>,
+    /// How much gold a pile on the floor is worth.
+    pub gold: Storage<u32>,
+}
+
+impl World {
+    /// A new entity without any components.
+    pub fn spawn(&mut self) -> EntityId {
+        match self.free.pop() {
+            Some(index) => EntityId { index, generation: self.generations[index] },
+            None => {
+                self.generations.push(0);
+                EntityId { index: self.generations.len() - 1, generation: 0 }
+            },
+        }
+    }
+
+    /// Whether `id` still refers to an entity.
+    pub fn contains(&self, id: EntityId) -> bool {
+        // a free slot's generation hasn't been handed out yet
+        self.generations.get(id.index) == Some(&id.generation)
+    }
+
+    /// Removes `id` along with all of its components. New kinds of components have to be
+    /// added here and in `duplicate`.
+    pub fn despawn(&mut self, id: EntityId) {
+        if !self.contains(id) {
+            return;
+        }
+        self.remove_position(id);
+        self.glyphs.remove(id);
+        self.names.remove(id);
+        self.blocks.remove(id);
+        self.fighters.remove(id);
+        self.ais.remove(id);
+        self.items.remove(id);
+        self.chests.remove(id);
+        self.stairs.remove(id);
+        self.paths.remove(id);
+        self.loot.remove(id);
+        self.gold.remove(id);
+        self.generations[id.index] += 1;
+        self.free.push(id.index);
+    }
+
+    /// A new entity with a copy of every component `id` has.
+    pub fn duplicate(&mut self, id: EntityId) -> EntityId {
+        let copy = self.spawn();
+        if let Some(&pos) = self.position(id) {
+            self.set_position(copy, pos);
+        }
+        self.glyphs.copy(id, copy);
+        self.names.copy(id, copy);
+        self.blocks.copy(id, copy);
+        self.fighters.copy(id, copy);
+        self.ais.copy(id, copy);
+        self.items.copy(id, copy);
+        self.chests.copy(id, copy);
+        self.stairs.copy(id, copy);
+        self.paths.copy(id, copy);
+        self.loot.copy(id, copy);
+        self.gold.copy(id, copy);
+        copy
+    }
+
+    pub fn name(&self, id: EntityId) -> &str {
+        self.names.get(id).map_or("", String::as_str)
+    }
+
+    pub fn position(&self, id: EntityId) -> Option<&Position> {
+        self.positions.get(id)
+    }
+
+    /// Puts `id` at `pos`, wherever it was before.
+    pub fn set_position(&mut self, id: EntityId, pos: Position) {
+        if !self.contains(id) {
+            return;
+        }
+        if let Some(old) = self.positions.get_mut(id) {
+            if old.depth == pos.depth {
+                *old = pos;
+                return;
+            }
+        }
+        self.remove_position(id);
+        while self.by_depth.len() <= pos.depth {
+            self.by_depth.push(Vec::new());
+        }
+        let level = &mut self.by_depth[pos.depth];
+        if let Err(i) = level.binary_search_by_key(&id.index, |other| other.index) {
+            level.insert(i, id);
+        }
+        self.positions.insert(id, pos);
+    }
+
+    /// Takes `id` off the dungeon floor, e.g. into the player's pack.
+    pub fn remove_position(&mut self, id: EntityId) -> Option<Position> {
+        let pos = self.positions.remove(id)?;
+        if let Some(level) = self.by_depth.get_mut(pos.depth) {
+            if let Ok(i) = level.binary_search_by_key(&id.index, |other| other.index) {
+                level.remove(i);
+            }
+        }
+        Some(pos)
+    }
+
+    /// Every entity on the level at `depth`, oldest slot first.
+    pub fn on_level(&self, depth: usize) -> Vec<EntityId> {
+        self.by_depth.get(depth).cloned().unwrap_or_default()
+    }
+
+    /// Every entity on the tile `(x, y)` of the level at `depth`, oldest slot first.
+    pub fn at(&self, depth: usize, (x, y): (i32, i32)) -> Vec<EntityId> {
+        self.by_depth.get(depth).map_or_else(Vec::new, |level| {
+            level.iter().cloned()
+                .filter(|&id| self.positions.get(id).is_some_and(|pos| pos.xy() == (x, y)))
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: i32, y: i32, depth: usize) -> Position {
+        Position { x, y, depth }
+    }
+
+    #[test]
+    fn stale_ids_miss_whatever_took_their_slot() {
+        let mut world = World::default();
+        let old = world.spawn();
+        world.names.insert(old, "goblin".to_string());
+        world.set_position(old, at(1, 1, 0));
+        world.despawn(old);
+
+        let new = world.spawn();
+        assert_eq!(new.index, old.index, "the slot is reused");
+        world.names.insert(new, "potion".to_string());
+        world.set_position(new, at(2, 2, 0));
+
+        assert!(!world.contains(old));
+        assert_eq!(world.name(old), "");
+        assert_eq!(world.position(old), None);
+        // writes through the old id don't reach the new entity either
+        world.set_position(old, at(5, 5, 1));
+        world.despawn(old);
+        assert_eq!(world.name(new), "potion");
+        assert_eq!(world.position(new), Some(&at(2, 2, 0)));
+        assert_eq!(world.on_level(0), vec![new]);
+        assert!(world.on_level(1).is_empty());
+    }
+
+    #[test]
+    fn moving_between_levels_keeps_them_indexed() {
+        let mut world = World::default();
+        let ids: Vec<EntityId> = (0..3).map(|_| world.spawn()).collect();
+        for (i, &id) in ids.iter().enumerate() {
+            world.set_position(id, at(i as i32, 0, 0));
+        }
+
+        world.set_position(ids[1], at(4, 4, 2));
+        assert_eq!(world.on_level(0), vec![ids[0], ids[2]]);
+        assert!(world.on_level(1).is_empty());
+        assert_eq!(world.on_level(2), vec![ids[1]]);
+        assert_eq!(world.at(2, (4, 4)), vec![ids[1]]);
+        assert!(world.at(0, (1, 0)).is_empty());
+
+        // moving within a level doesn't list it twice
+        world.set_position(ids[1], at(5, 4, 2));
+        assert_eq!(world.on_level(2), vec![ids[1]]);
+        assert_eq!(world.at(2, (5, 4)), vec![ids[1]]);
+
+        // and coming back puts it in slot order again
+        world.set_position(ids[1], at(1, 0, 0));
+        assert_eq!(world.on_level(0), ids);
+        assert!(world.on_level(2).is_empty());
+
+        world.remove_position(ids[0]);
+        assert_eq!(world.on_level(0), vec![ids[1], ids[2]]);
+        assert!(world.at(0, (0, 0)).is_empty());
+    }
+}
@@ -0,0 +1,82 @@
+extern crate giftrogue;
+
+use std::env;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use giftrogue::data::GameData;
+use giftrogue::game::{Action, GameEvent, GameState};
+use giftrogue::messages::{print_message, Category, Messages};
+use giftrogue::options::GameOptions;
+use giftrogue::replay::{fingerprint, Replay};
+use giftrogue::save::{load_game, save_game};
+
+const SEED: u64 = 7;
+
+const ACTIONS: [Action; 5] = [Action::Move(1, 0), Action::Move(0, 1), Action::Wait, Action::Move(-1, 0), Action::Move(0, -1)];
+
+/// A file in the temp dir that's only used by the test called `name`.
+fn temp_file(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("giftrogue-{}-{}.json", name, std::process::id()))
+}
+
+fn new_game() -> GameState {
+    let mut state = GameState::new(SEED, GameOptions::default(), Rc::new(GameData::default()));
+    state.step(Action::Start);
+    state
+}
+
+fn play(state: &mut GameState, steps: usize) -> Vec<GameEvent> {
+    ACTIONS.iter().cycle().take(steps).flat_map(|&action| state.step(action)).collect()
+}
+
+#[test]
+fn a_loaded_game_carries_on_like_the_saved_one() {
+    let path = temp_file("round-trip");
+    let mut state = new_game();
+    play(&mut state, 50);
+    let mut messages = Messages::default();
+    print_message(&mut messages, "Still here.", Category::System);
+
+    save_game(&path, &mut state, &messages).unwrap();
+    let (mut loaded, loaded_messages) = load_game(&path, state.data.clone()).unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(fingerprint(&loaded), fingerprint(&state));
+    assert_eq!(loaded_messages.history().map(|m| m.text.clone()).collect::<Vec<_>>(), vec!["Still here."]);
+    // the RNG was saved too, so both keep rolling the same dice
+    assert_eq!(play(&mut loaded, 100), play(&mut state, 100));
+    assert_eq!(fingerprint(&loaded), fingerprint(&state));
+}
+
+#[test]
+fn a_replay_notices_when_the_game_went_differently() {
+    let mut state = new_game();
+    let mut replay = Replay::new(SEED, GameOptions::default());
+    for &action in ACTIONS.iter().cycle().take(30) {
+        replay.record("test", action);
+        state.step(action);
+    }
+    replay.finish(&state);
+    assert_eq!(replay.matches(&state), Some(true));
+
+    // played back with one input too many, it ends up somewhere else
+    let mut other = new_game();
+    for input in &replay.inputs {
+        other.step(input.action);
+    }
+    assert_eq!(replay.matches(&other), Some(true));
+    other.step(Action::Wait);
+    assert_eq!(replay.matches(&other), Some(false));
+}
+
+#[test]
+fn replays_from_another_version_are_rejected() {
+    let path = temp_file("replay-version");
+    let mut replay = Replay::new(SEED, GameOptions::default());
+    replay.version += 1;
+    replay.save(&path).unwrap();
+    let loaded = Replay::load(&path);
+    let _ = std::fs::remove_file(&path);
+    assert!(loaded.is_err());
+}
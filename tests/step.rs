@@ -0,0 +1,315 @@
+extern crate giftrogue;
+
+use std::rc::Rc;
+
+use giftrogue::data::GameData;
+use giftrogue::entity::Position;
+use giftrogue::events::publish;
+use giftrogue::game::{Action, AttackKind, GameEvent, GameStage, GameState};
+use giftrogue::item::{self, Effect};
+use giftrogue::map::{Map, Tile};
+use giftrogue::options::GameOptions;
+use giftrogue::replay::fingerprint;
+use giftrogue::stats::Statistics;
+use giftrogue::world::EntityId;
+
+const SEED: u64 = 42;
+
+/// A game from `SEED`, past the title screen.
+fn new_game() -> GameState {
+    let mut state = GameState::new(SEED, GameOptions::default(), Rc::new(GameData::default()));
+    state.step(Action::Start);
+    state
+}
+
+/// Puts a goblin on the first free tile next to the player, returning it and the direction
+/// it's in.
+fn spawn_goblin(state: &mut GameState) -> (EntityId, (i32, i32)) {
+    let data = state.data.clone();
+    let goblin = data.monsters.iter().find(|m| m.name == "Goblin").expect("bundled monsters have a goblin");
+    let (x, y) = state.player_pos();
+    for &(dx, dy) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+        let pos = Position { x: x + dx, y: y + dy, depth: state.depth };
+        if let Some(id) = goblin.spawn(&mut state.world, &mut state.levels[state.depth].map, pos) {
+            return (id, (dx, dy));
+        }
+    }
+    panic!("no room for a goblin next to the player");
+}
+
+/// Puts one of the bundled items with `effect` in the player's pack, returning it.
+fn give_item(state: &mut GameState, effect: Effect) -> EntityId {
+    let data = state.data.clone();
+    let template = data.items.iter().find(|i| i.effect == effect).expect("bundled items have one");
+    // made on a map of its own, since it goes straight into the pack
+    let id = template.spawn(&mut state.world, &mut Map::new(1, 1, Tile::empty()), Position { x: 0, y: 0, depth: 0 }).unwrap();
+    state.inventory.add(&mut state.world, id).unwrap();
+    id
+}
+
+#[test]
+fn same_seed_and_actions_give_the_same_game() {
+    let actions = [Action::Move(1, 0), Action::Move(0, 1), Action::Wait, Action::Move(-1, 0), Action::Move(0, -1)];
+    let run = || {
+        let mut state = new_game();
+        let events: Vec<GameEvent> = actions.iter().cycle().take(200)
+            .flat_map(|&action| state.step(action))
+            .collect();
+        (fingerprint(&state), events)
+    };
+    assert_eq!(run(), run());
+}
+
+#[test]
+fn moves_are_a_single_step() {
+    let mut state = new_game();
+    let (x, y) = state.player_pos();
+    for &(dx, dy) in &[(3, 0), (0, -2), (2, 2), (0, 0)] {
+        assert!(state.step(Action::Move(dx, dy)).is_empty());
+        assert_eq!(state.player_pos(), (x, y));
+    }
+    assert_eq!(state.turn, 0);
+}
+
+#[test]
+fn moving_into_a_wall_stays_put() {
+    let mut state = new_game();
+    let (x, y) = state.player_pos();
+    state.levels[state.depth].map.set(x + 1, y, Tile::wall());
+
+    let events = state.step(Action::Move(1, 0));
+    assert_eq!(state.player_pos(), (x, y));
+    assert!(events.iter().any(|e| match *e {
+        GameEvent::Message(ref text, _) => text.contains("the wall"),
+        _ => false,
+    }));
+}
+
+#[test]
+fn moving_into_a_monster_attacks_it() {
+    let mut state = new_game();
+    let from = state.player_pos();
+    let (goblin, (dx, dy)) = spawn_goblin(&mut state);
+    let hp = state.world.fighters.get(goblin).unwrap().hp;
+
+    let events = state.step(Action::Move(dx, dy));
+    assert_eq!(state.player_pos(), from);
+    assert!(events.iter().any(|e| match *e {
+        GameEvent::Attacked { ref target, by_player, .. } => by_player && target == "Goblin",
+        _ => false,
+    }));
+    assert!(state.world.fighters.get(goblin).is_none_or(|f| f.hp < hp));
+}
+
+#[test]
+fn dying_ends_the_game() {
+    let mut state = new_game();
+    spawn_goblin(&mut state);
+    {
+        let player = state.world.fighters.get_mut(state.player).unwrap();
+        player.hp = 1;
+        player.defense = 0;
+    }
+
+    let mut events = Vec::new();
+    for _ in 0..20 {
+        events.extend(state.step(Action::Wait));
+        if state.stage == GameStage::GameOver {
+            break;
+        }
+    }
+    assert_eq!(state.stage, GameStage::GameOver);
+    assert!(events.contains(&GameEvent::StageChanged(GameStage::GameOver)));
+    // nothing but starting over is allowed once the player is dead
+    assert!(state.step(Action::Move(1, 0)).is_empty());
+}
+
+#[test]
+fn stairs_can_be_taken_straight_back() {
+    let mut state = new_game();
+    // put the player right next to the stairs down
+    let exit = state.levels[0].exit.expect("the first level has stairs down");
+    let (dx, dy) = *[(1, 0), (-1, 0), (0, 1), (0, -1)].iter()
+        .find(|&&(dx, dy)| !state.levels[0].map.get(exit.0 - dx, exit.1 - dy).blocks_movement)
+        .expect("the stairs down can be walked up to");
+    let (x, y) = state.player_pos();
+    state.levels[0].map.get_mut(x, y).blocks_movement = false;
+    state.levels[0].map.get_mut(exit.0 - dx, exit.1 - dy).blocks_movement = true;
+    state.world.set_position(state.player, Position { x: exit.0 - dx, y: exit.1 - dy, depth: 0 });
+
+    state.step(Action::Move(dx, dy));
+    assert_eq!(state.depth, 1);
+    let (x, y) = state.player_pos();
+    let entrance = state.levels[1].entrance;
+    assert!((entrance.0 - x).abs() + (entrance.1 - y).abs() == 1, "arrived next to the stairs up");
+
+    state.step(Action::Move(entrance.0 - x, entrance.1 - y));
+    assert_eq!(state.depth, 0);
+}
+
+#[test]
+fn explored_tiles_stay_explored_after_monster_turns() {
+    let mut state = new_game();
+    {
+        let map = &mut state.levels[state.depth].map;
+        for x in 0..map.width {
+            for y in 0..map.height {
+                map.get_mut(x, y).explored = true;
+            }
+        }
+    }
+    if let Some(player) = state.world.fighters.get_mut(state.player) {
+        player.hp = 10_000;
+    }
+
+    for _ in 0..150 {
+        state.step(Action::Wait);
+    }
+    let map = &state.levels[state.depth].map;
+    for x in 0..map.width {
+        for y in 0..map.height {
+            assert!(map.get(x, y).explored, "({}, {}) was forgotten", x, y);
+        }
+    }
+}
+
+#[test]
+fn level_up_is_announced_once() {
+    let mut state = new_game();
+    let (goblin, (dx, dy)) = spawn_goblin(&mut state);
+    state.world.fighters.get_mut(goblin).unwrap().hp = 1;
+    let needed = state.xp_to_level_up();
+    state.world.fighters.get_mut(state.player).unwrap().xp = needed - 1;
+
+    let announced = |events: Vec<GameEvent>| events.iter().filter(|e| match **e {
+        GameEvent::Message(ref text, _) => text.contains("battle skills"),
+        _ => false,
+    }).count();
+    assert_eq!(announced(state.step(Action::Move(dx, dy))), 1);
+    assert!(state.can_level_up());
+    for _ in 0..5 {
+        assert_eq!(announced(state.step(Action::Wait)), 0);
+    }
+}
+
+#[test]
+fn spells_aimed_off_the_map_are_not_cast() {
+    let mut state = new_game();
+    let scroll = give_item(&mut state, Effect::Fireball);
+
+    let (x, y) = state.player_pos();
+
+    for &(x, y) in &[(-1, 500), (x, -1), (state.levels[state.depth].map.width, y)] {
+        let events = state.step(Action::UseItemAt(0, x, y));
+        assert!(events.iter().any(|e| match *e {
+            GameEvent::Message(ref text, _) => text.contains("can't see"),
+            _ => false,
+        }));
+    }
+    assert_eq!(state.inventory.items, vec![scroll]);
+}
+
+#[test]
+fn spell_damage_is_counted_like_melee() {
+    let mut state = new_game();
+    spawn_goblin(&mut state);
+    give_item(&mut state, Effect::Fireball);
+
+    // a fireball at the player's feet burns both of them
+    let (x, y) = state.player_pos();
+    let events = state.step(Action::UseItemAt(0, x, y));
+    let burned = |to_player: bool| events.iter().any(|e| match *e {
+        GameEvent::Attacked { ref target, by_player, kind: AttackKind::Fireball, damage: 8, .. } => {
+            by_player != to_player && (to_player || target == "Goblin")
+        },
+        _ => false,
+    });
+    assert!(burned(false), "the goblin gets burned");
+    assert!(burned(true), "so does the player");
+
+    let mut stats = Statistics::default();
+    publish(&events, &state, &mut [&mut stats]);
+    assert_eq!(stats.damage_dealt, 8);
+    assert!(stats.damage_taken >= 8);
+}
+
+#[test]
+fn items_are_described_from_their_template() {
+    let mut state = new_game();
+    let potion = give_item(&mut state, Effect::Heal);
+    let description = state.data.item("healing potion").and_then(|t| t.description.clone()).unwrap();
+    assert_eq!(item::describe(&state.data, &state.world, potion), format!("healing potion. {}", description));
+
+    // without one, it's made up from what the item does
+    let mut data = GameData::default();
+    for template in &mut data.items {
+        template.description = None;
+    }
+    assert_eq!(item::describe(&data, &state.world, potion), "healing potion. Drinking it restores 3 hp.");
+}
+
+#[test]
+fn thrown_items_hitting_a_monster_count_as_damage_dealt() {
+    let mut state = new_game();
+    let (_, (dx, dy)) = spawn_goblin(&mut state);
+    let key = give_item(&mut state, Effect::Unlock);
+    let name = state.world.name(key).to_string();
+
+    let index = state.inventory.items.iter().position(|&id| id == key).unwrap();
+    let events = state.step(Action::ThrowItem(index, dx, dy));
+    assert!(events.iter().any(|e| match *e {
+        GameEvent::Attacked { ref target, by_player: true, kind: AttackKind::Thrown(ref item), .. } => {
+            target == "Goblin" && *item == name
+        },
+        _ => false,
+    }));
+
+    let mut stats = Statistics::default();
+    publish(&events, &state, &mut [&mut stats]);
+    assert!(stats.damage_dealt > 0);
+}
+
+#[test]
+fn thrown_items_land_in_front_of_the_chest() {
+    let mut state = new_game();
+    let key = give_item(&mut state, Effect::Unlock);
+    let (x, y) = state.player_pos();
+    let depth = state.depth;
+
+    // clear a lane east of the player with the chest at its end
+    let chest = state.world.chests.iter().map(|(id, _)| id).next().expect("the first level has the chest");
+    let old = *state.world.position(chest).unwrap();
+    state.levels[old.depth].map.get_mut(old.x, old.y).blocks_movement = false;
+    for dx in 1..3 {
+        for id in state.world.at(depth, (x + dx, y)) {
+            if state.world.fighters.has(id) {
+                state.world.despawn(id);
+            }
+        }
+        state.levels[depth].map.set(x + dx, y, Tile::empty());
+    }
+    state.levels[depth].map.set(x + 3, y, Tile::entity());
+    state.world.set_position(chest, Position { x: x + 3, y, depth });
+
+    let index = state.inventory.items.iter().position(|&id| id == key).unwrap();
+    state.step(Action::ThrowItem(index, 1, 0));
+    let landed = state.world.at(depth, (x + 2, y)).into_iter()
+        .any(|id| state.world.items.get(id).is_some_and(|i| i.effect == Effect::Unlock));
+    assert!(landed, "the key lands in front of the chest");
+    assert!(!state.levels[depth].map.get(x + 2, y).blocks_movement, "the key can be picked up again");
+}
+
+#[test]
+fn diagonal_throws_need_diagonal_movement() {
+    let mut state = new_game();
+    give_item(&mut state, Effect::Unlock);
+    let items = state.inventory.items.clone();
+
+    let events = state.step(Action::ThrowItem(0, 1, 1));
+    assert!(events.iter().any(|e| match *e {
+        GameEvent::Message(ref text, _) => text.contains("throw straight"),
+        _ => false,
+    }));
+    assert_eq!(state.inventory.items, items);
+    assert_eq!(state.turn, 0);
+}